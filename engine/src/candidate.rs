@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use ipc::ipc_proto::Candidate;
+
+// 1〜9のキーで選べるように、1ページに9個まで出す
+pub const PAGE_SIZE: usize = 9;
+
+// 候補ウィンドウに出す候補と、選んでいる候補
+// ページ送りはここで決めて、候補ウィンドウは今のページを表示するだけ
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandidateList {
+    candidates: Vec<Candidate>,
+    selected: usize,
+    page_size: usize,
+}
+
+impl Default for CandidateList {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl CandidateList {
+    pub fn new(candidates: Vec<Candidate>) -> Self {
+        CandidateList {
+            candidates,
+            selected: 0,
+            page_size: PAGE_SIZE,
+        }
+    }
+
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_candidate(&self) -> Option<&Candidate> {
+        self.candidates.get(self.selected)
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    // 選んでいる候補があるページ、0から数える
+    pub fn page(&self) -> usize {
+        self.selected / self.page_size
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.candidates.len().div_ceil(self.page_size)
+    }
+
+    // 今のページにある候補の、全体での範囲
+    pub fn page_range(&self) -> Range<usize> {
+        let start = self.page() * self.page_size;
+        start..(start + self.page_size).min(self.candidates.len())
+    }
+
+    pub fn page_candidates(&self) -> &[Candidate] {
+        &self.candidates[self.page_range()]
+    }
+
+    // 今のページの何番目を強調するか
+    pub fn highlighted(&self) -> usize {
+        self.selected % self.page_size
+    }
+
+    // stepが負なら前の候補、端まで行ったら反対側に戻る
+    pub fn move_by(&mut self, step: isize) {
+        if self.candidates.is_empty() {
+            return;
+        }
+        let count = self.candidates.len() as isize;
+        self.selected = (self.selected as isize + step).rem_euclid(count) as usize;
+    }
+
+    // ページを送る、強調する行はなるべくそのまま
+    // 最後のページより後ろは最初のページに戻る
+    pub fn move_page(&mut self, step: isize) {
+        let page_count = self.page_count() as isize;
+        if page_count == 0 {
+            return;
+        }
+        let page = (self.page() as isize + step).rem_euclid(page_count) as usize;
+        self.selected = (page * self.page_size + self.highlighted()).min(self.candidates.len() - 1);
+    }
+
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.candidates.len() {
+            return false;
+        }
+        self.selected = index;
+        true
+    }
+
+    // 今のページの、1から数えてnumber番目の候補の全体でのindex
+    pub fn index_on_page(&self, number: usize) -> Option<usize> {
+        if number == 0 || number > self.page_candidates().len() {
+            return None;
+        }
+        Some(self.page_range().start + number - 1)
+    }
+}
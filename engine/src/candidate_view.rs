@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+use crate::candidate::CandidateList;
+
+// 候補ウィンドウ (webview) とのやりとりの版
+// CandidateViewやRendererMessageの形を変えたら上げて、ime/src/candidate_window.htmlも合わせる
+pub const RENDERER_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+// 候補ウィンドウに表示する内容、JSONにしてwebviewに渡す
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CandidateView {
+    pub version: u32,
+    // 今のページの候補
+    pub candidates: Vec<CandidateRow>,
+    // 強調する行 (今のページの何番目か)
+    pub highlighted: usize,
+    pub page: PageInfo,
+    pub theme: Theme,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CandidateRow {
+    // 全体での候補のindex、選ばれたらこれを返してもらう
+    pub index: usize,
+    // 選ぶときに押す数字
+    pub label: String,
+    pub surface: String,
+    // なければ空
+    pub annotation: String,
+}
+
+// ページは1から数える
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PageInfo {
+    pub current: usize,
+    pub count: usize,
+    pub total_candidates: usize,
+}
+
+impl CandidateView {
+    pub fn new(candidates: &CandidateList, theme: Theme) -> Self {
+        let start = candidates.page_range().start;
+        CandidateView {
+            version: RENDERER_VERSION,
+            candidates: candidates
+                .page_candidates()
+                .iter()
+                .enumerate()
+                .map(|(row, candidate)| CandidateRow {
+                    index: start + row,
+                    label: (row + 1).to_string(),
+                    surface: candidate.surface.clone(),
+                    annotation: candidate.annotation.clone(),
+                })
+                .collect(),
+            highlighted: candidates.highlighted(),
+            page: PageInfo {
+                current: candidates.page() + 1,
+                count: candidates.page_count(),
+                total_candidates: candidates.len(),
+            },
+            theme,
+        }
+    }
+
+    // JSONはそのままJavaScriptの式として使える
+    // 候補の文字がスクリプトやHTMLとして解釈されないように、<>&と行区切り文字も\uでエスケープする
+    pub fn to_json(&self) -> String {
+        let json = serde_json::to_string(self).expect("a view model always serializes");
+        let mut escaped = String::with_capacity(json.len());
+        for c in json.chars() {
+            match c {
+                '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                    escaped.push_str(&format!("\\u{:04x}", c as u32));
+                }
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    // webviewで実行するスクリプト、文字列の中に埋め込まずに値として渡す
+    pub fn render_script(&self) -> String {
+        format!("window.renderCandidates({})", self.to_json())
+    }
+}
+
+// webviewからwindow.ipc.postMessageで届くメッセージ
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RendererMessage {
+    // ページを読み込んだ、versionが違えば何も表示しない
+    Ready { version: u32 },
+    // 候補がクリックされた、indexは全体での候補のindex
+    Select { index: usize },
+    // 表示できなかった
+    Error { message: String },
+}
+
+impl RendererMessage {
+    // 知らないメッセージや壊れたメッセージはNone
+    pub fn parse(message: &str) -> Option<Self> {
+        serde_json::from_str(message).ok()
+    }
+}
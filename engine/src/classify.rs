@@ -0,0 +1,89 @@
+use crate::composition::State;
+use crate::key::{self, Key, Modifiers};
+
+// IMEから見たキーの種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyClass {
+    // 文字を入力するキー (英数字、記号、テンキー)
+    Printable,
+    Space,
+    // Enter / Escape / Backspace / Delete
+    Editing,
+    // 矢印、Home / End、PageUp / PageDown、Tab
+    Navigation,
+    // Ctrl / Altと一緒に押されたキー (Ctrl+Cなど)
+    Shortcut,
+    // 半角/全角、カタカナひらがな、英数、IMEオン/オフ
+    ImeToggle,
+    // Shift / Ctrl / Alt / Winキー単体、CapsLock
+    Modifier,
+    // F6〜F10、入力中の文字列をひらがなやカタカナ、英数字にする
+    Transform,
+    // ファンクションキーなど
+    Other,
+}
+
+pub fn classify(key: Key, modifiers: Modifiers) -> KeyClass {
+    let virtual_key = key.virtual_key();
+
+    // 修飾キー単体とIMEの切り替えは、修飾キーの状態に関係なく決まる
+    match virtual_key {
+        key::VK_SHIFT
+        | key::VK_CONTROL
+        | key::VK_MENU
+        | key::VK_CAPITAL
+        | key::VK_LWIN
+        | key::VK_RWIN
+        | key::VK_LSHIFT..=key::VK_RMENU => return KeyClass::Modifier,
+        key::VK_KANA
+        | key::VK_IME_ON
+        | key::VK_KANJI
+        | key::VK_IME_OFF
+        | key::VK_DBE_ALPHANUMERIC..=key::VK_DBE_DBCSCHAR => return KeyClass::ImeToggle,
+        _ => {}
+    }
+
+    if modifiers.ctrl || modifiers.alt {
+        return KeyClass::Shortcut;
+    }
+
+    match key {
+        Key::Char(_) => KeyClass::Printable,
+        Key::Space => KeyClass::Space,
+        Key::Enter | Key::Escape | Key::Backspace | Key::Delete => KeyClass::Editing,
+        Key::Tab
+        | Key::Left
+        | Key::Right
+        | Key::Up
+        | Key::Down
+        | Key::Home
+        | Key::End
+        | Key::PageUp
+        | Key::PageDown => KeyClass::Navigation,
+        Key::Other(
+            key::VK_NUMPAD0..=key::VK_DIVIDE
+            | key::VK_OEM_1..=key::VK_OEM_3
+            | key::VK_OEM_4..=key::VK_OEM_8
+            | key::VK_OEM_102,
+        ) => KeyClass::Printable,
+        Key::Other(key::VK_F6..=key::VK_F10) => KeyClass::Transform,
+        Key::Other(_) => KeyClass::Other,
+    }
+}
+
+// キーをIMEが食べるか (falseならアプリにそのまま渡す)
+pub fn should_eat(class: KeyClass, state: State) -> bool {
+    match state {
+        // 入力中でなければ、文字を打ち始めるキーだけ
+        State::Idle => class == KeyClass::Printable,
+        // 入力中の文字列があるときは、編集に使うキーはアプリに渡さない
+        State::Composing | State::Converting | State::CandidateSelecting => matches!(
+            class,
+            KeyClass::Printable
+                | KeyClass::Space
+                | KeyClass::Editing
+                | KeyClass::Navigation
+                | KeyClass::Transform
+        ),
+    }
+}
@@ -0,0 +1,70 @@
+use crate::composition::InputMethod;
+use crate::mode::InputMode;
+
+// TF_CONVERSIONMODE_* (IME_CMODE_*と同じ値)
+pub const CONVERSION_ALPHANUMERIC: u32 = 0x0;
+pub const CONVERSION_NATIVE: u32 = 0x1;
+pub const CONVERSION_KATAKANA: u32 = 0x2;
+pub const CONVERSION_FULLSHAPE: u32 = 0x8;
+pub const CONVERSION_ROMAN: u32 = 0x10;
+
+// キーボードのコンパートメントの値
+// GUID_COMPARTMENT_KEYBOARD_OPENCLOSEとGUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compartments {
+    pub open: bool,
+    pub conversion: u32,
+}
+
+impl Compartments {
+    // 直接入力はIMEオフにして、変換モードにはオンにしたときに戻るモードを残す
+    pub fn new(mode: InputMode, on_mode: InputMode, input_method: InputMethod) -> Self {
+        let shown = if mode.is_direct() { on_mode } else { mode };
+        let mut conversion = conversion_mode(shown);
+        if shown.is_kana() && input_method == InputMethod::Romaji {
+            conversion |= CONVERSION_ROMAN;
+        }
+        Compartments {
+            open: !mode.is_direct(),
+            conversion,
+        }
+    }
+
+    // アプリやタスクバーが書いた値を入力モードにする
+    // ローマ字入力かどうかは言語バーで選ぶので、ROMANは見ない
+    pub fn mode(&self) -> InputMode {
+        if !self.open {
+            return InputMode::Direct;
+        }
+        self.shown_mode()
+    }
+
+    // IMEをオンにしたときに戻るモード、閉じていても変換モードに残っている
+    // 変換モードが直接入力ならわからないのでNone
+    pub fn on_mode(&self) -> Option<InputMode> {
+        Some(self.shown_mode()).filter(|mode| !mode.is_direct())
+    }
+
+    fn shown_mode(&self) -> InputMode {
+        let native = self.conversion & CONVERSION_NATIVE != 0;
+        let katakana = self.conversion & CONVERSION_KATAKANA != 0;
+        let full_shape = self.conversion & CONVERSION_FULLSHAPE != 0;
+        match (native, katakana, full_shape) {
+            (true, false, _) => InputMode::Hiragana,
+            (true, true, true) => InputMode::Katakana,
+            (true, true, false) => InputMode::HalfWidthKatakana,
+            (false, _, true) => InputMode::FullWidthAlphanumeric,
+            (false, _, false) => InputMode::Direct,
+        }
+    }
+}
+
+fn conversion_mode(mode: InputMode) -> u32 {
+    match mode {
+        InputMode::Hiragana => CONVERSION_NATIVE | CONVERSION_FULLSHAPE,
+        InputMode::Katakana => CONVERSION_NATIVE | CONVERSION_KATAKANA | CONVERSION_FULLSHAPE,
+        InputMode::HalfWidthKatakana => CONVERSION_NATIVE | CONVERSION_KATAKANA,
+        InputMode::FullWidthAlphanumeric => CONVERSION_FULLSHAPE,
+        InputMode::Direct => CONVERSION_ALPHANUMERIC,
+    }
+}
@@ -0,0 +1,702 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use ipc::client::ConverterClient;
+use ipc::error::Result;
+use ipc::ipc_proto::{
+    Candidate, CandidatesRequest, ConversionRequest, ConversionResponse, KeyEvent,
+    SelectCandidateRequest,
+};
+
+use crate::candidate::CandidateList;
+use crate::classify;
+use crate::kana;
+use crate::key::{self, Key};
+use crate::layout::Layout;
+use crate::mode::{self, InputMode};
+use crate::preedit::{Attribute, Preedit, Segment};
+use crate::romaji::{RomajiComposer, RomajiTable};
+use crate::transform::{self, Transform};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    // 入力中の文字列がない
+    Idle,
+    // 読みを入力している
+    Composing,
+    // 変換した (最初の候補を表示している)
+    Converting,
+    // 候補を選んでいる
+    CandidateSelecting,
+}
+
+// 読みの入力方法
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputMethod {
+    #[default]
+    Romaji,
+    // JISかな配列で直接かなを入力する
+    Kana,
+}
+
+// エンジンからアダプタ (TSF) への指示
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    StartComposition,
+    SetPreedit(Preedit),
+    // 入力中の文字列をtextで置き換えて確定する、compositionも終わる
+    CommitText(String),
+    // 何も確定せずにcompositionを終わる
+    EndComposition,
+    // 注目している文節の候補、今のページを表示する
+    ShowCandidates(CandidateList),
+    HideCandidates,
+    // 候補ウィンドウを入力中の文字列の位置に合わせる
+    MoveCandidates,
+    // 切り替えキーで入力モードが変わった
+    SetMode(InputMode),
+}
+
+// 変換した文節
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clause {
+    pub reading: String,
+    // サーバーが選んだ変換結果、候補をまだもらっていないときに表示する
+    pub surface: String,
+    // 注目したことのない文節は、候補をまだもらっていないので空
+    pub candidates: CandidateList,
+}
+
+impl Clause {
+    // 今表示している変換結果
+    // 文節の先頭だけの候補なら、残りは読みのまま表示する
+    // consumed_lengthが0の候補 (古いサーバー) は文節全体の候補として扱う
+    pub fn text(&self) -> String {
+        match self.candidates.selected_candidate() {
+            Some(candidate) if candidate.consumed_length == 0 => candidate.surface.clone(),
+            Some(candidate) => {
+                let rest: String = self
+                    .reading
+                    .chars()
+                    .skip(candidate.consumed_length as usize)
+                    .collect();
+                candidate.surface.clone() + &rest
+            }
+            None => self.surface.clone(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.reading.chars().count()
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Outcome {
+    // falseならキーはそのままアプリに渡す
+    pub handled: bool,
+    pub actions: Vec<Action>,
+}
+
+impl Outcome {
+    fn handled(actions: Vec<Action>) -> Self {
+        Outcome {
+            handled: true,
+            actions,
+        }
+    }
+
+    fn pass(actions: Vec<Action>) -> Self {
+        Outcome {
+            handled: false,
+            actions,
+        }
+    }
+}
+
+// キー入力から、compositionをどうするかを決める
+// 読みの入力はローマ字テーブルを使ってここで処理し、サーバーには変換するときだけ問い合わせる
+pub struct CompositionEngine {
+    state: State,
+    layout: Layout,
+    input_method: InputMethod,
+    mode: InputMode,
+    // IMEをオンにしたときに戻るモード、with_on_modeで言語バーなどと共有できる
+    on_mode: Rc<Cell<InputMode>>,
+    composer: RomajiComposer,
+    // F6〜F10で変えた表示、読みを編集すると元に戻る
+    transform: Option<Transform>,
+    clauses: Vec<Clause>,
+    // 注目している文節、候補ウィンドウにはこの文節の候補を出す
+    focused: usize,
+}
+
+impl Default for CompositionEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompositionEngine {
+    pub fn new() -> Self {
+        CompositionEngine {
+            state: State::Idle,
+            layout: Layout::default(),
+            input_method: InputMethod::default(),
+            mode: InputMode::default(),
+            on_mode: Rc::new(Cell::new(InputMode::default())),
+            composer: RomajiComposer::default(),
+            transform: None,
+            clauses: Vec::new(),
+            focused: 0,
+        }
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    // IMEをオンにしたときに戻るモードを、外と同じ場所に持つ
+    pub fn with_on_mode(mut self, on_mode: Rc<Cell<InputMode>>) -> Self {
+        self.on_mode = on_mode;
+        self
+    }
+
+    pub fn on_mode(&self) -> InputMode {
+        self.on_mode.get()
+    }
+
+    pub fn with_romaji_table(mut self, table: RomajiTable) -> Self {
+        self.composer.set_table(table);
+        self
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    pub fn input_method(&self) -> InputMethod {
+        self.input_method
+    }
+
+    // 入力途中のローマ字は確定させてから切り替える
+    pub fn set_input_method(&mut self, input_method: InputMethod) {
+        if self.input_method != input_method && self.state == State::Composing {
+            self.composer.flush();
+        }
+        self.input_method = input_method;
+    }
+
+    pub fn mode(&self) -> InputMode {
+        self.mode
+    }
+
+    // 言語バーなどから入力モードを変える
+    // 入力中の文字列は、直接入力になるなら確定し、そうでなければ新しいモードで表示し直す
+    pub fn set_mode(&mut self, mode: InputMode) -> Vec<Action> {
+        if mode == self.mode {
+            return Vec::new();
+        }
+        let previous = std::mem::replace(&mut self.mode, mode);
+        if !mode.is_direct() {
+            self.on_mode.set(mode);
+        }
+
+        match self.state {
+            State::Idle => Vec::new(),
+            State::Composing if mode.is_direct() => {
+                self.composer.flush();
+                let text = self.transformed(previous);
+                self.clear();
+                vec![Action::CommitText(text), Action::HideCandidates]
+            }
+            State::Composing => vec![Action::SetPreedit(self.preedit())],
+            State::Converting | State::CandidateSelecting if mode.is_direct() => {
+                let text = self.converted_text();
+                self.clear();
+                vec![Action::CommitText(text), Action::HideCandidates]
+            }
+            State::Converting | State::CandidateSelecting => Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    // 未確定のローマ字も含めた読み
+    pub fn reading(&self) -> String {
+        self.composer.text()
+    }
+
+    pub fn clauses(&self) -> &[Clause] {
+        &self.clauses
+    }
+
+    // 変換中でなければNone
+    pub fn focused_clause(&self) -> Option<usize> {
+        match self.state {
+            State::Converting | State::CandidateSelecting => Some(self.focused),
+            State::Idle | State::Composing => None,
+        }
+    }
+
+    // 注目している文節の候補
+    pub fn candidates(&self) -> &[Candidate] {
+        self.clauses
+            .get(self.focused)
+            .map_or(&[], |clause| clause.candidates.candidates())
+    }
+
+    // 変換中でなければNone
+    pub fn selected(&self) -> Option<usize> {
+        match self.state {
+            State::Converting | State::CandidateSelecting => self
+                .clauses
+                .get(self.focused)
+                .map(|clause| clause.candidates.selected()),
+            State::Idle | State::Composing => None,
+        }
+    }
+
+    pub fn preedit(&self) -> Preedit {
+        match self.state {
+            State::Idle => Preedit::default(),
+            State::Composing => Preedit::single(&self.composing_text(), Attribute::Input),
+            State::Converting | State::CandidateSelecting => {
+                let segments: Vec<Segment> = self
+                    .clauses
+                    .iter()
+                    .enumerate()
+                    .map(|(index, clause)| Segment {
+                        text: clause.text(),
+                        attribute: if index == self.focused {
+                            Attribute::Focused
+                        } else {
+                            Attribute::Converted
+                        },
+                    })
+                    .collect();
+                let caret = segments.iter().map(|s| s.text.chars().count()).sum();
+                Preedit { segments, caret }
+            }
+        }
+    }
+
+    // 今の状態でこのキーを食べるか、OnTestKeyDownで使う
+    pub fn wants_key(&self, event: &KeyEvent) -> bool {
+        if mode::switch(event, self.mode, self.on_mode.get()).is_some() {
+            return true;
+        }
+        if self.mode.is_direct() {
+            return false;
+        }
+        let class = classify::classify(Key::from_event(event), event.modifiers());
+        classify::should_eat(class, self.state)
+    }
+
+    // エラーのときは状態を変えずに返す
+    pub fn handle_key(&mut self, client: &dyn ConverterClient, event: KeyEvent) -> Result<Outcome> {
+        // 入力する文字はキーボード配列で決める
+        let event = match self.layout.character(&event) {
+            Some(character) => event.with_character(character),
+            None => event,
+        };
+
+        if let Some(mode) = mode::switch(&event, self.mode, self.on_mode.get()) {
+            let mut actions = self.set_mode(mode);
+            actions.push(Action::SetMode(mode));
+            return Ok(Outcome::handled(actions));
+        }
+        if self.mode.is_direct() {
+            return Ok(Outcome::pass(Vec::new()));
+        }
+
+        match self.state {
+            State::Idle => Ok(self.handle_idle(&event)),
+            State::Composing => self.handle_composing(client, &event),
+            State::Converting | State::CandidateSelecting => self.handle_converting(client, &event),
+        }
+    }
+
+    // 候補ウィンドウや数字キーで、注目している文節の候補をindex番目に決める
+    // 候補ウィンドウは閉じる、サーバーに学習してもらうのは確定したとき
+    pub fn select_candidate(&mut self, index: usize) -> Vec<Action> {
+        let Some(focused) = self.focused_clause() else {
+            return Vec::new();
+        };
+        if !self.clauses[focused].candidates.select(index) {
+            return Vec::new();
+        }
+        self.state = State::Converting;
+        vec![Action::SetPreedit(self.preedit()), Action::HideCandidates]
+    }
+
+    // compositionが外から終わらされたときなど、状態を捨てる
+    pub fn reset(&mut self) -> Vec<Action> {
+        let actions = match self.state {
+            State::Idle => Vec::new(),
+            _ => vec![Action::EndComposition, Action::HideCandidates],
+        };
+        self.clear();
+        actions
+    }
+
+    fn handle_idle(&mut self, event: &KeyEvent) -> Outcome {
+        if !self.input(event) {
+            return Outcome::pass(Vec::new());
+        }
+
+        self.state = State::Composing;
+        let mut actions = vec![Action::StartComposition];
+        actions.extend(self.composing_actions());
+        Outcome::handled(actions)
+    }
+
+    fn handle_composing(
+        &mut self,
+        client: &dyn ConverterClient,
+        event: &KeyEvent,
+    ) -> Result<Outcome> {
+        let key = Key::from_event(event);
+        if let Some(transform) = Transform::from_virtual_key(key.virtual_key()) {
+            return Ok(Outcome::handled(self.apply_transform(transform)));
+        }
+        match key {
+            Key::Space => {
+                // エラーのときは未確定のローマ字も残したいので、flushは変換できてから
+                let reading = self.composer.flushed_text();
+                let response = client.convert(ConversionRequest::from_reading(&reading))?;
+                self.composer.flush();
+                self.transform = None;
+
+                // 候補がなければ読みのまま
+                if response.candidates.is_empty() {
+                    return Ok(Outcome::handled(self.composing_actions()));
+                }
+                self.clauses = clauses_from(response, &reading, 0);
+                self.focused = 0;
+                self.state = State::Converting;
+                Ok(Outcome::handled(self.conversion_actions()))
+            }
+            // 読みをそのまま確定する
+            Key::Enter => Ok(Outcome::handled(self.commit_reading())),
+            // 読みの中でカーソルは動かせないので、確定してからアプリに渡してカーソルを動かしてもらう
+            Key::Left | Key::Right | Key::Home | Key::End | Key::Delete => {
+                Ok(Outcome::pass(self.commit_reading()))
+            }
+            // 入力を取り消す
+            Key::Escape => {
+                self.clear();
+                Ok(Outcome::handled(cancel_actions()))
+            }
+            Key::Backspace => {
+                self.transform = None;
+                self.composer.backspace();
+                // 読みが全部消えたらcompositionも終わる
+                if self.composer.is_empty() {
+                    self.clear();
+                    return Ok(Outcome::handled(cancel_actions()));
+                }
+                Ok(Outcome::handled(self.composing_actions()))
+            }
+            _ if self.input(event) => Ok(Outcome::handled(self.composing_actions())),
+            _ => Ok(Outcome::handled(Vec::new())),
+        }
+    }
+
+    fn commit_reading(&mut self) -> Vec<Action> {
+        self.composer.flush();
+        let text = self.composing_text();
+        self.clear();
+        vec![Action::CommitText(text), Action::HideCandidates]
+    }
+
+    // 入力中の文字列をまとめて変える、変換中なら変換をやめて読みに戻す
+    fn apply_transform(&mut self, transform: Transform) -> Vec<Action> {
+        self.state = State::Composing;
+        self.clauses.clear();
+        self.focused = 0;
+        self.composer.flush();
+        self.transform = Some(transform);
+        self.composing_actions()
+    }
+
+    fn handle_converting(
+        &mut self,
+        client: &dyn ConverterClient,
+        event: &KeyEvent,
+    ) -> Result<Outcome> {
+        let key = Key::from_event(event);
+        if let Some(transform) = Transform::from_virtual_key(key.virtual_key()) {
+            return Ok(Outcome::handled(self.apply_transform(transform)));
+        }
+        // 候補ウィンドウが出ていれば、数字キーで今のページの候補を選ぶ
+        if self.state == State::CandidateSelecting {
+            if let Some(number) = candidate_number(key) {
+                let candidates = &self.clauses[self.focused].candidates;
+                let actions = match candidates.index_on_page(number) {
+                    Some(index) => self.select_candidate(index),
+                    None => Vec::new(),
+                };
+                return Ok(Outcome::handled(actions));
+            }
+        }
+        let shift = event.modifiers().shift;
+        match key {
+            Key::Space | Key::Down => Ok(Outcome::handled(
+                self.move_selection(|candidates| candidates.move_by(1)),
+            )),
+            Key::Up => Ok(Outcome::handled(
+                self.move_selection(|candidates| candidates.move_by(-1)),
+            )),
+            Key::PageDown => Ok(Outcome::handled(
+                self.move_selection(|candidates| candidates.move_page(1)),
+            )),
+            Key::PageUp => Ok(Outcome::handled(
+                self.move_selection(|candidates| candidates.move_page(-1)),
+            )),
+            // Shiftと一緒なら注目している文節を縮める・伸ばす
+            Key::Left if shift => Ok(Outcome::handled(self.resize(client, -1)?)),
+            Key::Right if shift => Ok(Outcome::handled(self.resize(client, 1)?)),
+            Key::Left if self.focused > 0 => {
+                Ok(Outcome::handled(self.focus(client, self.focused - 1)?))
+            }
+            Key::Right if self.focused + 1 < self.clauses.len() => {
+                Ok(Outcome::handled(self.focus(client, self.focused + 1)?))
+            }
+            Key::Enter => Ok(Outcome::handled(self.commit(client))),
+            // 変換をやめて読みに戻す
+            Key::Escape | Key::Backspace => {
+                self.state = State::Composing;
+                self.clauses.clear();
+                self.focused = 0;
+                Ok(Outcome::handled(self.composing_actions()))
+            }
+            // 選んでいる候補を確定して、次の入力を始める
+            _ if self.typed(event).is_some() => {
+                let mut actions = self.commit(client);
+                let next = self.handle_idle(event);
+                actions.extend(next.actions);
+                Ok(Outcome {
+                    handled: next.handled,
+                    actions,
+                })
+            }
+            _ => Ok(Outcome::handled(Vec::new())),
+        }
+    }
+
+    // 注目している文節の候補を選び直して、候補ウィンドウを出す
+    fn move_selection(&mut self, step: impl FnOnce(&mut CandidateList)) -> Vec<Action> {
+        let clause = &mut self.clauses[self.focused];
+        if clause.candidates.is_empty() {
+            return Vec::new();
+        }
+        step(&mut clause.candidates);
+        self.state = State::CandidateSelecting;
+        self.conversion_actions()
+    }
+
+    // 注目する文節を変える、候補をまだもらっていなければサーバーに問い合わせる
+    fn focus(&mut self, client: &dyn ConverterClient, focused: usize) -> Result<Vec<Action>> {
+        if self.clauses[focused].candidates.is_empty() {
+            let response = client.candidates(CandidatesRequest {
+                reading: self.clauses[focused].reading.clone(),
+            })?;
+            self.clauses[focused].candidates = CandidateList::new(response.candidates);
+        }
+        self.focused = focused;
+        self.state = State::Converting;
+        Ok(self.conversion_actions())
+    }
+
+    // 注目している文節の長さを1文字変えて、それより後ろをサーバーに区切り直してもらう
+    // 前の文節は選んだ候補のまま残す
+    fn resize(&mut self, client: &dyn ConverterClient, delta: isize) -> Result<Vec<Action>> {
+        let length = self.clauses[self.focused].len() as isize + delta;
+        let rest: usize = self.clauses[self.focused..].iter().map(Clause::len).sum();
+        if length <= 0 || length as usize > rest {
+            return Ok(Vec::new());
+        }
+
+        let mut clause_lengths = self.clause_lengths(self.focused);
+        clause_lengths.push(length as u32);
+        let request = ConversionRequest::from_reading(self.composer.text())
+            .with_clauses(clause_lengths, self.focused);
+        let response = client.convert(request)?;
+        // 文節を返さないサーバーでは区切りを変えられない
+        if response.clauses.len() <= self.focused {
+            return Ok(Vec::new());
+        }
+
+        let clauses = clauses_from(response, &self.composer.text(), self.focused);
+        self.clauses.truncate(self.focused);
+        self.clauses.extend(clauses.into_iter().skip(self.focused));
+        self.state = State::Converting;
+        Ok(self.conversion_actions())
+    }
+
+    // 先頭からcount個の文節の長さ
+    fn clause_lengths(&self, count: usize) -> Vec<u32> {
+        self.clauses[..count]
+            .iter()
+            .map(|clause| clause.len() as u32)
+            .collect()
+    }
+
+    fn converted_text(&self) -> String {
+        self.clauses.iter().map(Clause::text).collect()
+    }
+
+    // 文字を入力するキーなら読みに加える
+    fn input(&mut self, event: &KeyEvent) -> bool {
+        let typed = self.typed(event);
+        if typed.is_some() {
+            self.transform = None;
+        }
+        match typed {
+            Some(Typed::Romaji(c)) => self.composer.push(c),
+            Some(Typed::Kana(c)) => self.composer.push_kana(c),
+            Some(Typed::Literal(c)) => self.composer.push_literal(c),
+            None => return false,
+        }
+        true
+    }
+
+    // キーが入力する文字、かな入力でもテンキーなどはローマ字と同じ扱い
+    fn typed(&self, event: &KeyEvent) -> Option<Typed> {
+        if Key::from_event(event) == Key::Space {
+            return None;
+        }
+        // 全角英数はローマ字を通さずに全角にする
+        if self.mode == InputMode::FullWidthAlphanumeric {
+            let character = event.character()?;
+            return transform::to_full_width(&character.to_string())
+                .chars()
+                .next()
+                .map(Typed::Literal);
+        }
+        let kana = match self.input_method {
+            InputMethod::Romaji => None,
+            InputMethod::Kana => kana::kana(event),
+        };
+        kana.map(Typed::Kana)
+            .or_else(|| event.character().map(Typed::Romaji))
+    }
+
+    // 入力モードに合わせた、入力中の文字列
+    fn composing_text(&self) -> String {
+        self.transformed(self.mode)
+    }
+
+    // F6〜F10で変えていればそれを、そうでなければ入力モードに合わせる
+    fn transformed(&self, mode: InputMode) -> String {
+        let text = self.composer.text();
+        match self.transform {
+            Some(transform) => transform.apply(&text, &self.composer.raw()),
+            None => mode.transform(&text),
+        }
+    }
+
+    fn composing_actions(&self) -> Vec<Action> {
+        vec![Action::SetPreedit(self.preedit()), Action::HideCandidates]
+    }
+
+    fn conversion_actions(&self) -> Vec<Action> {
+        vec![
+            Action::SetPreedit(self.preedit()),
+            Action::ShowCandidates(self.clauses[self.focused].candidates.clone()),
+            Action::MoveCandidates,
+        ]
+    }
+
+    // 選んでいる候補を確定する、サーバーにはどの文節の候補を選んだかを1回ずつ伝えて学習してもらう
+    // 学習できなくても確定はする
+    fn commit(&mut self, client: &dyn ConverterClient) -> Vec<Action> {
+        for clause in &self.clauses {
+            // 候補をもらっていない文節は、サーバーが選んだ変換結果のまま
+            let candidate = match clause.candidates.selected_candidate() {
+                Some(candidate) => candidate.clone(),
+                None => Candidate::new(clause.surface.clone(), clause.reading.clone()),
+            };
+            let _ = client.select_candidate(SelectCandidateRequest {
+                selected_candidate_index: clause.candidates.selected() as i32,
+                candidate: Some(candidate),
+            });
+        }
+
+        let text = self.converted_text();
+        self.clear();
+        vec![Action::CommitText(text), Action::HideCandidates]
+    }
+
+    fn clear(&mut self) {
+        self.state = State::Idle;
+        self.composer.clear();
+        self.transform = None;
+        self.clauses.clear();
+        self.focused = 0;
+    }
+}
+
+// サーバーの応答から文節を作る、候補はfocusedの文節に付ける
+// 文節を返さないサーバーなら、読み全体を1つの文節にする
+fn clauses_from(response: ConversionResponse, reading: &str, focused: usize) -> Vec<Clause> {
+    let mut clauses: Vec<Clause> = response
+        .clauses
+        .iter()
+        .map(|clause| Clause {
+            reading: clause.reading.clone(),
+            surface: clause.surface.clone(),
+            candidates: CandidateList::default(),
+        })
+        .collect();
+    let candidates = response.candidates;
+    if clauses.is_empty() {
+        clauses.push(Clause {
+            reading: reading.to_string(),
+            surface: candidates
+                .first()
+                .map(|candidate| candidate.surface.clone())
+                .unwrap_or_default(),
+            candidates: CandidateList::default(),
+        });
+    }
+    if let Some(clause) = clauses.get_mut(focused) {
+        clause.candidates = CandidateList::new(candidates);
+    }
+    clauses
+}
+
+// 1〜9のキー (テンキーも) なら、その数字
+fn candidate_number(key: Key) -> Option<usize> {
+    match key {
+        Key::Char(c @ '1'..='9') => c.to_digit(10).map(|number| number as usize),
+        Key::Other(virtual_key @ key::VK_NUMPAD1..=key::VK_NUMPAD9) => {
+            Some((virtual_key - key::VK_NUMPAD0) as usize)
+        }
+        _ => None,
+    }
+}
+
+enum Typed {
+    Romaji(char),
+    Kana(char),
+    // 変換せずにそのまま入れる
+    Literal(char),
+}
+
+// 入力中の文字列を消してからcompositionを終わる
+fn cancel_actions() -> Vec<Action> {
+    vec![
+        Action::SetPreedit(Preedit::default()),
+        Action::EndComposition,
+        Action::HideCandidates,
+    ]
+}
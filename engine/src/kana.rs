@@ -0,0 +1,56 @@
+use ipc::ipc_proto::KeyEvent;
+
+use crate::key;
+
+pub const DAKUTEN: char = '゛';
+pub const HANDAKUTEN: char = '゜';
+
+// JISかな配列の (仮想キーコード, シフトなし, シフトあり)
+#[rustfmt::skip]
+const KANA_KEYS: [(u32, char, char); 48] = [
+    (0x31, 'ぬ', 'ぬ'), (0x32, 'ふ', 'ふ'), (0x33, 'あ', 'ぁ'), (0x34, 'う', 'ぅ'), (0x35, 'え', 'ぇ'),
+    (0x36, 'お', 'ぉ'), (0x37, 'や', 'ゃ'), (0x38, 'ゆ', 'ゅ'), (0x39, 'よ', 'ょ'), (0x30, 'わ', 'を'),
+    (0xBD, 'ほ', 'ほ'), (0xDE, 'へ', 'へ'), (0xDC, 'ー', 'ー'),
+    (0x51, 'た', 'た'), (0x57, 'て', 'て'), (0x45, 'い', 'ぃ'), (0x52, 'す', 'す'), (0x54, 'か', 'か'),
+    (0x59, 'ん', 'ん'), (0x55, 'な', 'な'), (0x49, 'に', 'に'), (0x4F, 'ら', 'ら'), (0x50, 'せ', 'せ'),
+    (0xC0, DAKUTEN, DAKUTEN), (0xDB, HANDAKUTEN, '「'),
+    (0x41, 'ち', 'ち'), (0x53, 'と', 'と'), (0x44, 'し', 'し'), (0x46, 'は', 'は'), (0x47, 'き', 'き'),
+    (0x48, 'く', 'く'), (0x4A, 'ま', 'ま'), (0x4B, 'の', 'の'), (0x4C, 'り', 'り'), (0xBB, 'れ', 'れ'),
+    (0xBA, 'け', 'け'), (0xDD, 'む', '」'),
+    (0x5A, 'つ', 'っ'), (0x58, 'さ', 'さ'), (0x43, 'そ', 'そ'), (0x56, 'ひ', 'ひ'), (0x42, 'こ', 'こ'),
+    (0x4E, 'み', 'み'), (0x4D, 'も', 'も'), (0xBC, 'ね', '、'), (0xBE, 'る', '。'), (0xBF, 'め', '・'),
+    (key::VK_OEM_102, 'ろ', 'ろ'),
+];
+
+// 「ろ」キー、layoutと同じくスキャンコードでも見る
+const SCAN_CODE_RO: u32 = 0x73;
+
+// かな入力でキーが入力するかな、かなを入力しないキーはNone
+pub fn kana(event: &KeyEvent) -> Option<char> {
+    let modifiers = event.modifiers();
+    if modifiers.ctrl || modifiers.alt {
+        return None;
+    }
+
+    let virtual_key = if event.scan_code == SCAN_CODE_RO {
+        key::VK_OEM_102
+    } else {
+        event.virtual_key
+    };
+    let &(_, plain, shifted) = KANA_KEYS.iter().find(|(vk, ..)| *vk == virtual_key)?;
+    Some(if modifiers.shift { shifted } else { plain })
+}
+
+// 濁点と半濁点を前の文字に付ける、付けられなければNone
+pub fn combine(base: char, mark: char) -> Option<char> {
+    match (mark, base) {
+        (DAKUTEN, 'う') => Some('ゔ'),
+        // 濁音は清音の1つ後ろ、半濁音は2つ後ろ
+        (DAKUTEN, 'か' | 'き' | 'く' | 'け' | 'こ')
+        | (DAKUTEN, 'さ' | 'し' | 'す' | 'せ' | 'そ')
+        | (DAKUTEN, 'た' | 'ち' | 'つ' | 'て' | 'と')
+        | (DAKUTEN, 'は' | 'ひ' | 'ふ' | 'へ' | 'ほ') => char::from_u32(base as u32 + 1),
+        (HANDAKUTEN, 'は' | 'ひ' | 'ふ' | 'へ' | 'ほ') => char::from_u32(base as u32 + 2),
+        _ => None,
+    }
+}
@@ -0,0 +1,135 @@
+// https://learn.microsoft.com/ja-jp/windows/win32/inputdev/virtual-key-codes
+pub const VK_BACK: u32 = 0x08;
+pub const VK_TAB: u32 = 0x09;
+pub const VK_RETURN: u32 = 0x0D;
+pub const VK_SHIFT: u32 = 0x10;
+pub const VK_CONTROL: u32 = 0x11;
+pub const VK_MENU: u32 = 0x12;
+pub const VK_CAPITAL: u32 = 0x14;
+pub const VK_KANA: u32 = 0x15;
+pub const VK_IME_ON: u32 = 0x16;
+pub const VK_KANJI: u32 = 0x19;
+pub const VK_IME_OFF: u32 = 0x1A;
+pub const VK_ESCAPE: u32 = 0x1B;
+pub const VK_CONVERT: u32 = 0x1C;
+pub const VK_NONCONVERT: u32 = 0x1D;
+pub const VK_SPACE: u32 = 0x20;
+pub const VK_PRIOR: u32 = 0x21;
+pub const VK_NEXT: u32 = 0x22;
+pub const VK_END: u32 = 0x23;
+pub const VK_HOME: u32 = 0x24;
+pub const VK_LEFT: u32 = 0x25;
+pub const VK_UP: u32 = 0x26;
+pub const VK_RIGHT: u32 = 0x27;
+pub const VK_DOWN: u32 = 0x28;
+pub const VK_DELETE: u32 = 0x2E;
+pub const VK_LWIN: u32 = 0x5B;
+pub const VK_RWIN: u32 = 0x5C;
+pub const VK_NUMPAD0: u32 = 0x60;
+pub const VK_NUMPAD1: u32 = 0x61;
+pub const VK_NUMPAD9: u32 = 0x69;
+pub const VK_DIVIDE: u32 = 0x6F;
+pub const VK_F1: u32 = 0x70;
+pub const VK_F6: u32 = 0x75;
+pub const VK_F7: u32 = 0x76;
+pub const VK_F8: u32 = 0x77;
+pub const VK_F9: u32 = 0x78;
+pub const VK_F10: u32 = 0x79;
+pub const VK_F24: u32 = 0x87;
+pub const VK_LSHIFT: u32 = 0xA0;
+pub const VK_RMENU: u32 = 0xA5;
+pub const VK_OEM_1: u32 = 0xBA;
+pub const VK_OEM_MINUS: u32 = 0xBD;
+pub const VK_OEM_3: u32 = 0xC0;
+pub const VK_OEM_4: u32 = 0xDB;
+pub const VK_OEM_8: u32 = 0xDF;
+pub const VK_OEM_102: u32 = 0xE2;
+// JISキーボードの英数 / カタカナひらがな / 半角全角
+pub const VK_DBE_ALPHANUMERIC: u32 = 0xF0;
+pub const VK_DBE_KATAKANA: u32 = 0xF1;
+pub const VK_DBE_HIRAGANA: u32 = 0xF2;
+pub const VK_DBE_SBCSCHAR: u32 = 0xF3;
+pub const VK_DBE_DBCSCHAR: u32 = 0xF4;
+
+pub use ipc::ipc_proto::{KeyEvent, Modifiers};
+
+// プラットフォームに依存しないキー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    // 文字を入力するキー (英字は小文字)
+    Char(char),
+    Space,
+    Enter,
+    Escape,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    // それ以外は仮想キーコードのまま持つ
+    Other(u32),
+}
+
+impl Key {
+    pub fn from_virtual_key(virtual_key: u32) -> Self {
+        match virtual_key {
+            0x30..=0x39 => Key::Char(char::from(virtual_key as u8)),
+            0x41..=0x5A => Key::Char(char::from(virtual_key as u8).to_ascii_lowercase()),
+            VK_OEM_MINUS => Key::Char('-'),
+            VK_SPACE => Key::Space,
+            VK_RETURN => Key::Enter,
+            VK_ESCAPE => Key::Escape,
+            VK_BACK => Key::Backspace,
+            VK_DELETE => Key::Delete,
+            VK_TAB => Key::Tab,
+            VK_LEFT => Key::Left,
+            VK_RIGHT => Key::Right,
+            VK_UP => Key::Up,
+            VK_DOWN => Key::Down,
+            VK_HOME => Key::Home,
+            VK_END => Key::End,
+            VK_PRIOR => Key::PageUp,
+            VK_NEXT => Key::PageDown,
+            other => Key::Other(other),
+        }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        Self::from_virtual_key(event.virtual_key)
+    }
+
+    pub fn virtual_key(&self) -> u32 {
+        match *self {
+            Key::Char('-') => VK_OEM_MINUS,
+            Key::Char(c) => c.to_ascii_uppercase() as u32,
+            Key::Space => VK_SPACE,
+            Key::Enter => VK_RETURN,
+            Key::Escape => VK_ESCAPE,
+            Key::Backspace => VK_BACK,
+            Key::Delete => VK_DELETE,
+            Key::Tab => VK_TAB,
+            Key::Left => VK_LEFT,
+            Key::Right => VK_RIGHT,
+            Key::Up => VK_UP,
+            Key::Down => VK_DOWN,
+            Key::Home => VK_HOME,
+            Key::End => VK_END,
+            Key::PageUp => VK_PRIOR,
+            Key::PageDown => VK_NEXT,
+            Key::Other(virtual_key) => virtual_key,
+        }
+    }
+}
+
+// 修飾キーなどなしで押されたことにする
+impl From<Key> for KeyEvent {
+    fn from(key: Key) -> Self {
+        KeyEvent::new(key.virtual_key())
+    }
+}
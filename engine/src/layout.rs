@@ -0,0 +1,159 @@
+use std::str::FromStr;
+
+use ipc::ipc_proto::KeyEvent;
+
+use crate::key;
+
+// 設定でキーボード配列を選ぶための環境変数 ("jis" か "us")
+pub const LAYOUT_ENV: &str = "AZOOKEY_KEYBOARD_LAYOUT";
+
+// 物理キーボードの配列
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    // JIS 106/109
+    #[default]
+    Jis,
+    // US 101
+    Us,
+}
+
+// VK_OEM_*キーの (シフトなし, シフトあり)
+#[rustfmt::skip]
+const JIS_SYMBOLS: [(u32, char, Option<char>); 11] = [
+    (0xBA, ':', Some('*')),
+    (0xBB, ';', Some('+')),
+    (0xBC, ',', Some('<')),
+    (0xBD, '-', Some('=')),
+    (0xBE, '.', Some('>')),
+    (0xBF, '/', Some('?')),
+    (0xC0, '@', Some('`')),
+    (0xDB, '[', Some('{')),
+    (0xDC, '\\', Some('|')), // ¥キー
+    (0xDD, ']', Some('}')),
+    (0xDE, '^', Some('~')),
+];
+
+#[rustfmt::skip]
+const US_SYMBOLS: [(u32, char, Option<char>); 11] = [
+    (0xBA, ';', Some(':')),
+    (0xBB, '=', Some('+')),
+    (0xBC, ',', Some('<')),
+    (0xBD, '-', Some('_')),
+    (0xBE, '.', Some('>')),
+    (0xBF, '/', Some('?')),
+    (0xC0, '`', Some('~')),
+    (0xDB, '[', Some('{')),
+    (0xDC, '\\', Some('|')),
+    (0xDD, ']', Some('}')),
+    (0xDE, '\'', Some('"')),
+];
+
+// 数字キーをシフトしたときの文字 (0から9の順)
+const JIS_SHIFTED_DIGITS: [Option<char>; 10] = [
+    None,
+    Some('!'),
+    Some('"'),
+    Some('#'),
+    Some('$'),
+    Some('%'),
+    Some('&'),
+    Some('\''),
+    Some('('),
+    Some(')'),
+];
+
+const US_SHIFTED_DIGITS: [Option<char>; 10] = [
+    Some(')'),
+    Some('!'),
+    Some('@'),
+    Some('#'),
+    Some('$'),
+    Some('%'),
+    Some('^'),
+    Some('&'),
+    Some('*'),
+    Some('('),
+];
+
+// JISキーボードの「ろ」キー
+const SCAN_CODE_RO: u32 = 0x73;
+
+impl Layout {
+    // 設定がなければdefaultを使う
+    pub fn configured(default: Layout) -> Layout {
+        std::env::var(LAYOUT_ENV)
+            .ok()
+            .and_then(|name| name.parse().ok())
+            .unwrap_or(default)
+    }
+
+    // キーが入力する文字、Ctrl / Altとの組み合わせや文字を入力しないキーはNone
+    pub fn character(&self, event: &KeyEvent) -> Option<char> {
+        let modifiers = event.modifiers();
+        if modifiers.ctrl || modifiers.alt {
+            return None;
+        }
+        let shift = modifiers.shift;
+
+        let virtual_key = event.virtual_key;
+        match virtual_key {
+            key::VK_SPACE => Some(' '),
+            0x30..=0x39 => {
+                let digit = (virtual_key - 0x30) as usize;
+                if shift {
+                    match self {
+                        Layout::Jis => JIS_SHIFTED_DIGITS[digit],
+                        Layout::Us => US_SHIFTED_DIGITS[digit],
+                    }
+                } else {
+                    char::from_digit(digit as u32, 10)
+                }
+            }
+            0x41..=0x5A => {
+                let c = char::from(virtual_key as u8);
+                if event.is_uppercase() {
+                    Some(c)
+                } else {
+                    Some(c.to_ascii_lowercase())
+                }
+            }
+            // テンキーはどちらの配列でも同じ
+            0x60..=0x69 => char::from_digit(virtual_key - 0x60, 10),
+            0x6A => Some('*'),
+            0x6B => Some('+'),
+            0x6D => Some('-'),
+            0x6E => Some('.'),
+            0x6F => Some('/'),
+            // 「ろ」キーはドライバによってVK_OEM_102でないこともあるので、スキャンコードでも見る
+            _ if *self == Layout::Jis
+                && (virtual_key == key::VK_OEM_102 || event.scan_code == SCAN_CODE_RO) =>
+            {
+                Some(if shift { '_' } else { '\\' })
+            }
+            _ => {
+                let symbols = match self {
+                    Layout::Jis => &JIS_SYMBOLS,
+                    Layout::Us => &US_SYMBOLS,
+                };
+                let &(_, plain, shifted) = symbols.iter().find(|(vk, ..)| *vk == virtual_key)?;
+                if shift {
+                    shifted
+                } else {
+                    Some(plain)
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "jis" | "106" | "109" => Ok(Layout::Jis),
+            "us" | "101" => Ok(Layout::Us),
+            _ => Err(format!("unknown keyboard layout: {}", name)),
+        }
+    }
+}
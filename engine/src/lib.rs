@@ -0,0 +1,14 @@
+// TSFに依存しない入力処理
+// Windows以外でもビルドできるので、ここにあるロジックはLinuxでテストする
+pub mod candidate;
+pub mod candidate_view;
+pub mod classify;
+pub mod compartment;
+pub mod composition;
+pub mod kana;
+pub mod key;
+pub mod layout;
+pub mod mode;
+pub mod preedit;
+pub mod romaji;
+pub mod transform;
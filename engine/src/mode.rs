@@ -0,0 +1,94 @@
+use ipc::ipc_proto::KeyEvent;
+
+use crate::key;
+use crate::transform;
+
+// 入力モード、言語バーと切り替えキーで変える
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputMode {
+    #[default]
+    Hiragana,
+    Katakana,
+    HalfWidthKatakana,
+    FullWidthAlphanumeric,
+    // 半角英数、IMEオフと同じでキーはそのままアプリに渡す
+    Direct,
+}
+
+impl InputMode {
+    pub const ALL: [InputMode; 5] = [
+        InputMode::Hiragana,
+        InputMode::Katakana,
+        InputMode::HalfWidthKatakana,
+        InputMode::FullWidthAlphanumeric,
+        InputMode::Direct,
+    ];
+
+    // 言語バーに表示する文字
+    pub fn label(&self) -> &'static str {
+        match self {
+            InputMode::Hiragana => "あ",
+            InputMode::Katakana => "カ",
+            InputMode::HalfWidthKatakana => "_ｶ",
+            InputMode::FullWidthAlphanumeric => "Ａ",
+            InputMode::Direct => "A",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            InputMode::Hiragana => "ひらがな",
+            InputMode::Katakana => "全角カタカナ",
+            InputMode::HalfWidthKatakana => "半角カタカナ",
+            InputMode::FullWidthAlphanumeric => "全角英数",
+            InputMode::Direct => "半角英数",
+        }
+    }
+
+    pub fn is_direct(&self) -> bool {
+        *self == InputMode::Direct
+    }
+
+    // ローマ字やかなで読みを入力するモードか
+    pub fn is_kana(&self) -> bool {
+        matches!(
+            self,
+            InputMode::Hiragana | InputMode::Katakana | InputMode::HalfWidthKatakana
+        )
+    }
+
+    // ひらがなの読みを、このモードで表示・確定する文字にする
+    pub fn transform(&self, reading: &str) -> String {
+        match self {
+            InputMode::Katakana => transform::to_katakana(reading),
+            InputMode::HalfWidthKatakana => transform::to_half_width_katakana(reading),
+            InputMode::Hiragana | InputMode::FullWidthAlphanumeric | InputMode::Direct => {
+                reading.to_string()
+            }
+        }
+    }
+}
+
+// 切り替えキーを押したあとのモード、切り替えキーでなければNone
+// on_modeはIMEをオンにしたときに戻るモード
+pub fn switch(event: &KeyEvent, mode: InputMode, on_mode: InputMode) -> Option<InputMode> {
+    let shift = event.modifiers().shift;
+    match event.virtual_key {
+        // 半角/全角
+        key::VK_KANJI | key::VK_DBE_SBCSCHAR | key::VK_DBE_DBCSCHAR => Some(if mode.is_direct() {
+            on_mode
+        } else {
+            InputMode::Direct
+        }),
+        key::VK_IME_ON => Some(on_mode),
+        key::VK_IME_OFF => Some(InputMode::Direct),
+        // カタカナひらがな、Shiftでカタカナ
+        key::VK_KANA | key::VK_DBE_HIRAGANA if shift => Some(InputMode::Katakana),
+        key::VK_KANA | key::VK_DBE_HIRAGANA => Some(InputMode::Hiragana),
+        key::VK_DBE_KATAKANA => Some(InputMode::Katakana),
+        // 英数、Shiftで全角英数
+        key::VK_DBE_ALPHANUMERIC if shift => Some(InputMode::FullWidthAlphanumeric),
+        key::VK_DBE_ALPHANUMERIC => Some(InputMode::Direct),
+        _ => None,
+    }
+}
@@ -0,0 +1,73 @@
+use std::ops::Range;
+
+// 表示属性 (display_attribute.rsのinput / converted / focusedに対応)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    // 未変換の読み
+    Input,
+    // 変換済み
+    Converted,
+    // 変換中で注目している部分
+    Focused,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub text: String,
+    pub attribute: Attribute,
+}
+
+// 入力中の文字列、属性ごとに区切って持つ
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preedit {
+    pub segments: Vec<Segment>,
+    // キャレットの位置 (文字数)
+    pub caret: usize,
+}
+
+impl Preedit {
+    // 全体が一つの属性で、キャレットが末尾にあるもの
+    pub fn single(text: &str, attribute: Attribute) -> Self {
+        Preedit {
+            segments: vec![Segment {
+                text: text.to_string(),
+                attribute,
+            }],
+            caret: text.chars().count(),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(|segment| segment.text.is_empty())
+    }
+
+    // TSFの範囲はUTF-16の単位なので、空でない区切りごとの範囲をUTF-16で返す
+    pub fn utf16_ranges(&self) -> Vec<(Range<usize>, Attribute)> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for segment in &self.segments {
+            let end = start + segment.text.encode_utf16().count();
+            if end > start {
+                ranges.push((start..end, segment.attribute));
+            }
+            start = end;
+        }
+        ranges
+    }
+
+    // キャレットの位置をUTF-16の単位にしたもの、末尾より後ろにはしない
+    pub fn utf16_caret(&self) -> usize {
+        self.text()
+            .chars()
+            .take(self.caret)
+            .map(char::len_utf16)
+            .sum()
+    }
+}
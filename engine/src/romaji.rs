@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::kana;
+
+// ユーザーのローマ字テーブルのパスを設定する環境変数
+pub const ROMAJI_TABLE_ENV: &str = "AZOOKEY_ROMAJI_TABLE";
+
+// 組み込みのテーブル、Google日本語入力 / Mozcと同じ形式
+const BUILTIN_TABLE: &str = include_str!("romaji.tsv");
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub input: String,
+    pub output: String,
+    // 出力のあとに入力に残す文字 (ttのt)
+    pub next: String,
+}
+
+#[derive(Debug)]
+pub enum TableError {
+    Io(io::Error),
+    // 1始まりの行番号
+    InvalidLine { line: usize, content: String },
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::Io(e) => write!(f, "failed to read romaji table: {}", e),
+            TableError::InvalidLine { line, content } => {
+                write!(f, "invalid romaji rule on line {}: {:?}", line, content)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TableError::Io(e) => Some(e),
+            TableError::InvalidLine { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for TableError {
+    fn from(e: io::Error) -> Self {
+        TableError::Io(e)
+    }
+}
+
+// ローマ字からひらがなへの変換規則
+#[derive(Clone, Debug)]
+pub struct RomajiTable {
+    rules: HashMap<String, Rule>,
+    // どれかの規則の入力の、途中までの文字列 (kyaに対するk、ky)
+    prefixes: HashSet<String>,
+}
+
+impl Default for RomajiTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl RomajiTable {
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_TABLE).expect("the builtin romaji table is valid")
+    }
+
+    // 設定されたファイルがあればそれを、読めなければ組み込みのテーブルを使う
+    pub fn configured() -> Self {
+        std::env::var_os(ROMAJI_TABLE_ENV)
+            .and_then(|path| Self::load(path).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TableError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    // 1行に1つ、「入力<TAB>出力」か「入力<TAB>出力<TAB>次の入力」
+    // 空行と#で始まる行は読み飛ばす、同じ入力が複数あれば後のものを使う
+    pub fn parse(text: &str) -> Result<Self, TableError> {
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split('\t');
+            let input = fields.next().unwrap_or_default();
+            let output = fields.next();
+            let next = fields.next().unwrap_or_default();
+            match output {
+                Some(output)
+                    if !input.is_empty()
+                        && (!output.is_empty() || !next.is_empty())
+                        && fields.next().is_none() =>
+                {
+                    rules.push(Rule {
+                        input: input.to_string(),
+                        output: output.to_string(),
+                        next: next.to_string(),
+                    });
+                }
+                _ => {
+                    return Err(TableError::InvalidLine {
+                        line: index + 1,
+                        content: line.to_string(),
+                    })
+                }
+            }
+        }
+        Ok(Self::from_rules(rules))
+    }
+
+    pub fn from_rules(rules: impl IntoIterator<Item = Rule>) -> Self {
+        let mut table = RomajiTable {
+            rules: HashMap::new(),
+            prefixes: HashSet::new(),
+        };
+        for rule in rules {
+            let mut prefix = String::new();
+            let mut chars = rule.input.chars();
+            chars.next_back();
+            for c in chars {
+                prefix.push(c);
+                table.prefixes.insert(prefix.clone());
+            }
+            table.rules.insert(rule.input.clone(), rule);
+        }
+        table
+    }
+
+    pub fn get(&self, input: &str) -> Option<&Rule> {
+        self.rules.get(input)
+    }
+
+    // inputの続きを入力すると、まだ別の規則に当てはまる可能性があるか
+    pub fn is_prefix(&self, input: &str) -> bool {
+        self.prefixes.contains(input)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+// かなになった部分と、それを入力したキーの文字 (かはka)
+#[derive(Clone, Debug)]
+struct Chunk {
+    kana: String,
+    raw: String,
+}
+
+// 入力中のローマ字を、確定したかなと未確定の部分に分けて持つ
+#[derive(Clone, Debug, Default)]
+pub struct RomajiComposer {
+    table: RomajiTable,
+    chunks: Vec<Chunk>,
+    // まだかなになっていない入力 (kyのように続きを待っているもの)
+    pending: String,
+}
+
+impl RomajiComposer {
+    pub fn new(table: RomajiTable) -> Self {
+        RomajiComposer {
+            table,
+            chunks: Vec::new(),
+            pending: String::new(),
+        }
+    }
+
+    pub fn table(&self) -> &RomajiTable {
+        &self.table
+    }
+
+    pub fn set_table(&mut self, table: RomajiTable) {
+        self.table = table;
+        self.clear();
+    }
+
+    // 表示する文字列 (「かk」のように未確定の部分も含む)
+    pub fn text(&self) -> String {
+        let mut text: String = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.kana.as_str())
+            .collect();
+        text.push_str(&self.pending);
+        text
+    }
+
+    // flushしたあとのtext()、自分は変えない
+    pub fn flushed_text(&self) -> String {
+        let mut text: String = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.kana.as_str())
+            .collect();
+        match self.table.get(&self.pending) {
+            Some(rule) => {
+                text.push_str(&rule.output);
+                text.push_str(&rule.next);
+            }
+            None => text.push_str(&self.pending),
+        }
+        text
+    }
+
+    // かなにする前の、入力したキーの文字 (F9 / F10で使う)
+    pub fn raw(&self) -> String {
+        let mut raw: String = self.chunks.iter().map(|chunk| chunk.raw.as_str()).collect();
+        raw.push_str(&self.pending);
+        raw
+    }
+
+    pub fn pending(&self) -> &str {
+        &self.pending
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.pending.is_empty()
+    }
+
+    pub fn push(&mut self, c: char) {
+        loop {
+            let mut input = self.pending.clone();
+            input.push(c);
+
+            // 続きがあるかもしれないので待つ (nに対するna)
+            if self.table.is_prefix(&input) {
+                self.pending = input;
+                return;
+            }
+            if let Some(rule) = self.table.get(&input).cloned() {
+                // 次の入力に残す文字は、次のかなのキーとして数える (ttaはっ + ta)
+                let raw = input.strip_suffix(rule.next.as_str()).unwrap_or(&input);
+                self.push_chunk(&rule.output, raw);
+                self.pending = rule.next.clone();
+                return;
+            }
+            // どの規則にも当てはまらない文字はそのまま
+            if self.pending.is_empty() {
+                self.push_chunk(&c.to_string(), &c.to_string());
+                return;
+            }
+            // 待っていた部分を確定させてから、cを入力し直す (nkはんk)
+            self.flush();
+        }
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.push(c);
+        }
+    }
+
+    // かな入力の文字を入れる、濁点と半濁点は前の文字に付けられれば付ける
+    pub fn push_kana(&mut self, c: char) {
+        self.flush();
+        if let Some(last) = self.chunks.last_mut() {
+            let combined = last
+                .kana
+                .chars()
+                .next_back()
+                .and_then(|base| kana::combine(base, c));
+            if let Some(combined) = combined {
+                last.kana.pop();
+                last.kana.push(combined);
+                last.raw.push(c);
+                return;
+            }
+        }
+        self.push_chunk(&c.to_string(), &c.to_string());
+    }
+
+    // 変換しない文字 (全角英数) を入れる
+    pub fn push_literal(&mut self, c: char) {
+        self.flush();
+        self.push_chunk(&c.to_string(), &c.to_string());
+    }
+
+    // 未確定の部分を確定させる、末尾のnはんになる
+    pub fn flush(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        match self.table.get(&pending).cloned() {
+            Some(rule) => {
+                let kana = format!("{}{}", rule.output, rule.next);
+                self.push_chunk(&kana, &pending);
+            }
+            None => self.push_chunk(&pending, &pending),
+        }
+    }
+
+    // 最後の1文字を消す、未確定の部分があればそちらから
+    // かなの一部を消したら、残りのキーはわからないのでかなのままにする (きゃからき)
+    pub fn backspace(&mut self) {
+        if self.pending.pop().is_some() {
+            return;
+        }
+        if let Some(last) = self.chunks.last_mut() {
+            last.kana.pop();
+            if last.kana.is_empty() {
+                self.chunks.pop();
+            } else {
+                last.raw = last.kana.clone();
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.pending.clear();
+    }
+
+    fn push_chunk(&mut self, kana: &str, raw: &str) {
+        if kana.is_empty() {
+            return;
+        }
+        self.chunks.push(Chunk {
+            kana: kana.to_string(),
+            raw: raw.to_string(),
+        });
+    }
+}
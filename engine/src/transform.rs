@@ -0,0 +1,150 @@
+// かなと英数字の文字種や幅を変える
+
+use crate::key;
+
+// 全角カタカナと半角カタカナの対応、濁点と半濁点はこれに分けてから変える
+const FULL_WIDTH: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
+const HALF_WIDTH: &str = "｡｢｣､･ｦｧｨｩｪｫｬｭｮｯｰｱｲｳｴｵｶｷｸｹｺｻｼｽｾｿﾀﾁﾂﾃﾄﾅﾆﾇﾈﾉﾊﾋﾌﾍﾎﾏﾐﾑﾒﾓﾔﾕﾖﾗﾘﾙﾚﾛﾜﾝﾞﾟ";
+// 濁点をつけられる清音 (濁音はその次の文字)、半濁点はハ行だけ (半濁音は2つ先)
+const VOICED: &str = "カキクケコサシスセソタチツテトハヒフヘホ";
+const SEMI_VOICED: &str = "ハヒフヘホ";
+
+// F6〜F10で、入力中の文字列をまとめて変える
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    Hiragana,
+    Katakana,
+    HalfWidthKatakana,
+    FullWidthAlphanumeric,
+    HalfWidthAlphanumeric,
+}
+
+impl Transform {
+    pub fn from_virtual_key(virtual_key: u32) -> Option<Self> {
+        match virtual_key {
+            key::VK_F6 => Some(Transform::Hiragana),
+            key::VK_F7 => Some(Transform::Katakana),
+            key::VK_F8 => Some(Transform::HalfWidthKatakana),
+            key::VK_F9 => Some(Transform::FullWidthAlphanumeric),
+            key::VK_F10 => Some(Transform::HalfWidthAlphanumeric),
+            _ => None,
+        }
+    }
+
+    // readingはひらがなの読み、rawはそれを入力したキーの文字
+    pub fn apply(&self, reading: &str, raw: &str) -> String {
+        match self {
+            // 半角カタカナが混じっていても全角にそろえる
+            Transform::Hiragana => to_hiragana(&to_full_width_katakana(reading)),
+            Transform::Katakana => to_katakana(&to_full_width_katakana(reading)),
+            Transform::HalfWidthKatakana => to_half_width_katakana(reading),
+            Transform::FullWidthAlphanumeric => to_full_width(raw),
+            Transform::HalfWidthAlphanumeric => to_half_width(raw),
+        }
+    }
+}
+
+pub fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' | 'ヽ' | 'ヾ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+pub fn to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+// 半角にない濁音は、清音と半角の濁点に分ける (ガはｶﾞ)
+pub fn to_half_width_katakana(text: &str) -> String {
+    let mut output = String::new();
+    for c in to_katakana(text).chars() {
+        match c {
+            'ガ' | 'ギ' | 'グ' | 'ゲ' | 'ゴ' | 'ザ' | 'ジ' | 'ズ' | 'ゼ' | 'ゾ' | 'ダ' | 'ヂ'
+            | 'ヅ' | 'デ' | 'ド' | 'バ' | 'ビ' | 'ブ' | 'ベ' | 'ボ' => {
+                push_half_width(&mut output, char::from_u32(c as u32 - 1).unwrap_or(c));
+                output.push('ﾞ');
+            }
+            'パ' | 'ピ' | 'プ' | 'ペ' | 'ポ' => {
+                push_half_width(&mut output, char::from_u32(c as u32 - 2).unwrap_or(c));
+                output.push('ﾟ');
+            }
+            'ヴ' => output.push_str("ｳﾞ"),
+            // 半角にない小さい文字は普通の大きさにする
+            'ヮ' => output.push('ﾜ'),
+            'ヵ' => output.push('ｶ'),
+            'ヶ' => output.push('ｹ'),
+            'ヰ' => output.push('ｲ'),
+            'ヱ' => output.push('ｴ'),
+            _ => push_half_width(&mut output, c),
+        }
+    }
+    output
+}
+
+// 半角の濁点と半濁点は前の文字とまとめる (ｶﾞはガ)、まとめられなければ全角の゛と゜にする
+pub fn to_full_width_katakana(text: &str) -> String {
+    let mut output = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let full = match HALF_WIDTH.chars().position(|half| half == c) {
+            Some(index) => FULL_WIDTH.chars().nth(index).unwrap_or(c),
+            None => {
+                output.push(c);
+                continue;
+            }
+        };
+        let merged = match chars.peek() {
+            Some('ﾞ') if full == 'ウ' => Some('ヴ'),
+            Some('ﾞ') if VOICED.contains(full) => char::from_u32(full as u32 + 1),
+            Some('ﾟ') if SEMI_VOICED.contains(full) => char::from_u32(full as u32 + 2),
+            _ => None,
+        };
+        match merged {
+            Some(merged) => {
+                chars.next();
+                output.push(merged);
+            }
+            None => output.push(full),
+        }
+    }
+    output
+}
+
+fn push_half_width(output: &mut String, c: char) {
+    match FULL_WIDTH.chars().position(|full| full == c) {
+        Some(index) => output.push(HALF_WIDTH.chars().nth(index).unwrap_or(c)),
+        None => output.push(to_half_width_char(c)),
+    }
+}
+
+// 英数字と記号を全角にする
+pub fn to_full_width(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            ' ' => '\u{3000}',
+            '!'..='~' => char::from_u32(c as u32 + 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+// 全角の英数字と記号を半角にする
+pub fn to_half_width(text: &str) -> String {
+    text.chars().map(to_half_width_char).collect()
+}
+
+fn to_half_width_char(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '！'..='～' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
@@ -0,0 +1,79 @@
+use engine::candidate::{CandidateList, PAGE_SIZE};
+use ipc::ipc_proto::Candidate;
+
+fn list(count: usize) -> CandidateList {
+    CandidateList::new(
+        (0..count)
+            .map(|i| Candidate::new(format!("候補{}", i), "こうほ"))
+            .collect(),
+    )
+}
+
+fn surfaces(list: &CandidateList) -> Vec<&str> {
+    list.page_candidates()
+        .iter()
+        .map(|candidate| candidate.surface.as_str())
+        .collect()
+}
+
+#[test]
+fn pages_hold_up_to_nine_candidates() {
+    let list = list(20);
+    assert_eq!(PAGE_SIZE, 9);
+    assert_eq!(list.page_count(), 3);
+    assert_eq!(list.page(), 0);
+    assert_eq!(list.page_range(), 0..9);
+    assert_eq!(list.highlighted(), 0);
+
+    assert_eq!(CandidateList::default().page_count(), 0);
+    assert!(CandidateList::default().page_candidates().is_empty());
+}
+
+#[test]
+fn moving_past_a_page_turns_it() {
+    let mut list = list(20).with_page_size(5);
+    list.move_by(6);
+    assert_eq!(list.selected(), 6);
+    assert_eq!(list.page(), 1);
+    assert_eq!(list.highlighted(), 1);
+    assert_eq!(
+        surfaces(&list),
+        ["候補5", "候補6", "候補7", "候補8", "候補9"]
+    );
+
+    // wraps around at both ends
+    list.move_by(-7);
+    assert_eq!(list.selected(), 19);
+    list.move_by(1);
+    assert_eq!(list.selected(), 0);
+}
+
+#[test]
+fn page_keys_keep_the_highlighted_row() {
+    let mut list = list(12).with_page_size(5);
+    list.move_by(3);
+    list.move_page(1);
+    assert_eq!(list.selected(), 8);
+
+    // the last page is short, so the last candidate is highlighted
+    list.move_page(1);
+    assert_eq!(list.selected(), 11);
+    assert_eq!(surfaces(&list), ["候補10", "候補11"]);
+
+    list.move_page(1);
+    assert_eq!(list.selected(), 1);
+    list.move_page(-1);
+    assert_eq!(list.selected(), 11);
+}
+
+#[test]
+fn numbers_pick_from_the_current_page() {
+    let mut list = list(12).with_page_size(5);
+    list.move_page(1);
+    assert_eq!(list.index_on_page(3), Some(7));
+
+    list.move_page(1);
+    assert_eq!(list.index_on_page(2), Some(11));
+    assert_eq!(list.index_on_page(3), None);
+    assert_eq!(list.index_on_page(0), None);
+}
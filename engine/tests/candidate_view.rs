@@ -0,0 +1,94 @@
+use engine::candidate::CandidateList;
+use engine::candidate_view::{CandidateView, RendererMessage, Theme, RENDERER_VERSION};
+use ipc::ipc_proto::Candidate;
+use serde_json::{json, Value};
+
+fn list(surfaces: &[&str]) -> CandidateList {
+    CandidateList::new(
+        surfaces
+            .iter()
+            .map(|surface| Candidate::new(*surface, "かん"))
+            .collect(),
+    )
+}
+
+#[test]
+fn the_view_holds_the_current_page() {
+    let mut candidates = CandidateList::new(
+        (0..12)
+            .map(|i| Candidate::new(format!("候補{}", i), "こうほ"))
+            .collect(),
+    );
+    candidates.move_by(10);
+    let view = CandidateView::new(&candidates, Theme::Dark);
+
+    let value: Value = serde_json::from_str(&view.to_json()).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "version": RENDERER_VERSION,
+            "candidates": [
+                { "index": 9, "label": "1", "surface": "候補9", "annotation": "" },
+                { "index": 10, "label": "2", "surface": "候補10", "annotation": "" },
+                { "index": 11, "label": "3", "surface": "候補11", "annotation": "" },
+            ],
+            "highlighted": 1,
+            "page": { "current": 2, "count": 2, "total_candidates": 12 },
+            "theme": "dark",
+        })
+    );
+}
+
+#[test]
+fn annotations_are_passed_along() {
+    let candidates = CandidateList::new(vec![
+        Candidate::new("株式会社", "かぶしきがいしゃ"),
+        Candidate::new("㈱", "かぶしきがいしゃ").with_annotation("環境依存"),
+    ]);
+    let view = CandidateView::new(&candidates, Theme::Light);
+    assert_eq!(view.candidates[1].annotation, "環境依存");
+    assert_eq!(view.theme, Theme::Light);
+}
+
+#[test]
+fn candidates_cannot_break_out_of_the_script() {
+    let nasty = [
+        "it's",
+        "a\\b",
+        "x,y",
+        "</script><script>alert(1)</script>",
+        "\u{2028}",
+    ];
+    let view = CandidateView::new(&list(&nasty), Theme::Light);
+    let script = view.render_script();
+
+    assert!(script.starts_with("window.renderCandidates({"));
+    assert!(!script.contains('<'));
+    assert!(!script.contains('>'));
+    assert!(!script.contains('\u{2028}'));
+    assert!(!script.contains("\\'"));
+
+    // the escaped JSON still reads back as the same text
+    let value: Value = serde_json::from_str(&view.to_json()).unwrap();
+    let surfaces: Vec<&str> = value["candidates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["surface"].as_str().unwrap())
+        .collect();
+    assert_eq!(surfaces, nasty);
+}
+
+#[test]
+fn renderer_messages_are_typed() {
+    assert_eq!(
+        RendererMessage::parse(r#"{"type":"ready","version":1}"#),
+        Some(RendererMessage::Ready { version: 1 })
+    );
+    assert_eq!(
+        RendererMessage::parse(r#"{"type":"select","index":10}"#),
+        Some(RendererMessage::Select { index: 10 })
+    );
+    assert_eq!(RendererMessage::parse(r#"{"type":"select"}"#), None);
+    assert_eq!(RendererMessage::parse("3"), None);
+}
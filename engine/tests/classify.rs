@@ -0,0 +1,115 @@
+use engine::classify::{classify, should_eat, KeyClass};
+use engine::composition::State;
+use engine::key::{Key, KeyEvent, Modifiers};
+
+const IDLE: [State; 1] = [State::Idle];
+const ACTIVE: [State; 3] = [
+    State::Composing,
+    State::Converting,
+    State::CandidateSelecting,
+];
+
+#[test]
+fn classifies_keys() {
+    let table = [
+        // letters, digits, symbols and the numpad type text
+        (0x41, Modifiers::NONE, KeyClass::Printable),
+        (0x41, Modifiers::SHIFT, KeyClass::Printable),
+        (0x35, Modifiers::NONE, KeyClass::Printable),
+        (0xBD, Modifiers::NONE, KeyClass::Printable),
+        (0xBA, Modifiers::NONE, KeyClass::Printable),
+        (0xDE, Modifiers::NONE, KeyClass::Printable),
+        (0xE2, Modifiers::NONE, KeyClass::Printable),
+        (0x61, Modifiers::NONE, KeyClass::Printable),
+        (0x20, Modifiers::NONE, KeyClass::Space),
+        (0x0D, Modifiers::NONE, KeyClass::Editing),
+        (0x1B, Modifiers::NONE, KeyClass::Editing),
+        (0x08, Modifiers::NONE, KeyClass::Editing),
+        (0x2E, Modifiers::NONE, KeyClass::Editing),
+        (0x25, Modifiers::NONE, KeyClass::Navigation),
+        (0x28, Modifiers::SHIFT, KeyClass::Navigation),
+        (0x24, Modifiers::NONE, KeyClass::Navigation),
+        (0x22, Modifiers::NONE, KeyClass::Navigation),
+        (0x09, Modifiers::NONE, KeyClass::Navigation),
+        // Ctrl+C, Alt+F, Ctrl+Left
+        (0x43, Modifiers::CTRL, KeyClass::Shortcut),
+        (0x46, Modifiers::ALT, KeyClass::Shortcut),
+        (0x25, Modifiers::CTRL, KeyClass::Shortcut),
+        (0x20, Modifiers::CTRL, KeyClass::Shortcut),
+        // Hankaku/Zenkaku, Kana, Eisu, IME On/Off
+        (0x19, Modifiers::NONE, KeyClass::ImeToggle),
+        (0xF3, Modifiers::NONE, KeyClass::ImeToggle),
+        (0xF4, Modifiers::NONE, KeyClass::ImeToggle),
+        (0x15, Modifiers::NONE, KeyClass::ImeToggle),
+        (0xF0, Modifiers::SHIFT, KeyClass::ImeToggle),
+        (0x16, Modifiers::NONE, KeyClass::ImeToggle),
+        (0x1A, Modifiers::NONE, KeyClass::ImeToggle),
+        (0x19, Modifiers::ALT, KeyClass::ImeToggle),
+        (0x10, Modifiers::SHIFT, KeyClass::Modifier),
+        (0x11, Modifiers::CTRL, KeyClass::Modifier),
+        (0xA4, Modifiers::ALT, KeyClass::Modifier),
+        (0x5B, Modifiers::NONE, KeyClass::Modifier),
+        (0x14, Modifiers::NONE, KeyClass::Modifier),
+        (0x70, Modifiers::NONE, KeyClass::Other),
+        (0x75, Modifiers::NONE, KeyClass::Transform),
+        (0x79, Modifiers::NONE, KeyClass::Transform),
+        (0x7A, Modifiers::NONE, KeyClass::Other),
+        (0x7B, Modifiers::NONE, KeyClass::Other),
+        (0x2D, Modifiers::NONE, KeyClass::Other),
+    ];
+
+    for (virtual_key, modifiers, expected) in table {
+        assert_eq!(
+            classify(Key::from_virtual_key(virtual_key), modifiers),
+            expected,
+            "vk {:#04x} with {:?}",
+            virtual_key,
+            modifiers
+        );
+    }
+}
+
+#[test]
+fn eats_keys_depending_on_the_state() {
+    let table: [(KeyClass, &[State], bool); 18] = [
+        (KeyClass::Printable, &IDLE, true),
+        (KeyClass::Space, &IDLE, false),
+        (KeyClass::Editing, &IDLE, false),
+        (KeyClass::Navigation, &IDLE, false),
+        (KeyClass::Shortcut, &IDLE, false),
+        (KeyClass::ImeToggle, &IDLE, false),
+        (KeyClass::Modifier, &IDLE, false),
+        (KeyClass::Transform, &IDLE, false),
+        (KeyClass::Other, &IDLE, false),
+        (KeyClass::Printable, &ACTIVE, true),
+        (KeyClass::Space, &ACTIVE, true),
+        (KeyClass::Editing, &ACTIVE, true),
+        (KeyClass::Navigation, &ACTIVE, true),
+        (KeyClass::Shortcut, &ACTIVE, false),
+        (KeyClass::ImeToggle, &ACTIVE, false),
+        (KeyClass::Modifier, &ACTIVE, false),
+        (KeyClass::Transform, &ACTIVE, true),
+        (KeyClass::Other, &ACTIVE, false),
+    ];
+
+    for (class, states, expected) in table {
+        for &state in states {
+            assert_eq!(
+                should_eat(class, state),
+                expected,
+                "{:?} while {:?}",
+                class,
+                state
+            );
+        }
+    }
+}
+
+#[test]
+fn shortcuts_pass_through_without_a_composition() {
+    let engine = engine::composition::CompositionEngine::new();
+    assert!(engine.wants_key(&KeyEvent::from(Key::Char('a'))));
+    assert!(!engine.wants_key(&KeyEvent::from(Key::Char('c')).with_modifiers(Modifiers::CTRL)));
+    assert!(!engine.wants_key(&KeyEvent::from(Key::Left)));
+    assert!(!engine.wants_key(&KeyEvent::from(Key::Other(0x70))));
+}
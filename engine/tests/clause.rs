@@ -0,0 +1,277 @@
+mod common;
+
+use engine::candidate::CandidateList;
+use engine::composition::{Action, CompositionEngine, State};
+use engine::key::{Key, KeyEvent, Modifiers};
+use engine::preedit::{Attribute, Preedit, Segment};
+use ipc::client::MockClient;
+use ipc::error::Error;
+use ipc::ipc_proto::{
+    request, Candidate, Clause, ConversionRequest, ConversionResponse, SelectCandidateRequest,
+};
+
+use common::press;
+
+// candidates are for the clause at `focused`
+fn response(clauses: &[(&str, &str)], focused: usize, candidates: &[&str]) -> ConversionResponse {
+    let reading = clauses[focused].0;
+    ConversionResponse {
+        converted_text: clauses.iter().map(|(reading, _)| *reading).collect(),
+        candidates: candidates
+            .iter()
+            .map(|surface| Candidate::new(*surface, reading))
+            .collect(),
+        clauses: clauses
+            .iter()
+            .map(|(reading, surface)| Clause {
+                reading: reading.to_string(),
+                surface: surface.to_string(),
+            })
+            .collect(),
+    }
+}
+
+// the answer to a candidates request for one clause
+fn clause_candidates(candidates: Vec<Candidate>) -> ConversionResponse {
+    ConversionResponse {
+        candidates,
+        ..Default::default()
+    }
+}
+
+fn last_request(client: &MockClient) -> ConversionRequest {
+    match client.requests().pop() {
+        Some(request::Payload::Convert(request)) => request,
+        other => panic!("expected a conversion, got {:?}", other),
+    }
+}
+
+// converts わたしのなまえ into 私|の|名前
+fn converting(client: &MockClient) -> CompositionEngine {
+    let mut engine = CompositionEngine::new();
+    for c in "watashinonamae".chars() {
+        engine.handle_key(client, Key::Char(c).into()).unwrap();
+    }
+    client.push_response(response(
+        &[("わたし", "私"), ("の", "の"), ("なまえ", "名前")],
+        0,
+        &["私", "渡し", "わたし"],
+    ));
+    press(&mut engine, client, Key::Space);
+    client.clear_requests();
+    engine
+}
+
+fn texts(engine: &CompositionEngine) -> Vec<String> {
+    engine
+        .clauses()
+        .iter()
+        .map(|clause| clause.text())
+        .collect()
+}
+
+fn surfaces(engine: &CompositionEngine) -> Vec<&str> {
+    engine
+        .candidates()
+        .iter()
+        .map(|candidate| candidate.surface.as_str())
+        .collect()
+}
+
+#[test]
+fn the_focused_clause_is_highlighted() {
+    let client = MockClient::new();
+    let engine = converting(&client);
+    assert_eq!(engine.state(), State::Converting);
+    assert_eq!(engine.focused_clause(), Some(0));
+    assert_eq!(
+        engine.preedit(),
+        Preedit {
+            segments: vec![
+                Segment {
+                    text: "私".to_string(),
+                    attribute: Attribute::Focused,
+                },
+                Segment {
+                    text: "の".to_string(),
+                    attribute: Attribute::Converted,
+                },
+                Segment {
+                    text: "名前".to_string(),
+                    attribute: Attribute::Converted,
+                },
+            ],
+            caret: 4,
+        }
+    );
+}
+
+#[test]
+fn arrows_move_between_clauses() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+    press(&mut engine, &client, Key::Space);
+    assert_eq!(texts(&engine), ["渡し", "の", "名前"]);
+
+    // candidates of a clause are asked for the first time it is focused
+    client.push_response(clause_candidates(vec![
+        Candidate::new("の", "の"),
+        Candidate::new("ノ", "の"),
+    ]));
+    client.push_response(clause_candidates(vec![
+        Candidate::new("名前", "なまえ"),
+        Candidate::new("なまえ", "なまえ"),
+    ]));
+    press(&mut engine, &client, Key::Right);
+    press(&mut engine, &client, Key::Right);
+    match client.requests().pop() {
+        Some(request::Payload::Candidates(request)) => assert_eq!(request.reading, "なまえ"),
+        other => panic!("expected a candidates request, got {:?}", other),
+    }
+    assert_eq!(engine.focused_clause(), Some(2));
+    assert_eq!(surfaces(&engine), ["名前", "なまえ"]);
+
+    // clauses keep their candidates, so no new request is made
+    client.clear_requests();
+    press(&mut engine, &client, Key::Left);
+    press(&mut engine, &client, Key::Left);
+    press(&mut engine, &client, Key::Left);
+    assert!(client.requests().is_empty());
+    assert_eq!(engine.focused_clause(), Some(0));
+    assert_eq!(engine.selected(), Some(1));
+
+    client.push_response(ConversionResponse::default());
+    let outcome = engine.handle_key(&client, Key::Enter.into()).unwrap();
+    assert_eq!(
+        outcome.actions[0],
+        Action::CommitText("渡しの名前".to_string())
+    );
+}
+
+#[test]
+fn shift_arrows_resize_the_focused_clause() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+
+    client.push_response(response(
+        &[("わたしの", "わたしの"), ("なまえ", "名前")],
+        0,
+        &["わたしの", "ワタシノ"],
+    ));
+    press(
+        &mut engine,
+        &client,
+        KeyEvent::from(Key::Right).with_modifiers(Modifiers::SHIFT),
+    );
+    let request = last_request(&client);
+    assert_eq!(request.clause_lengths, [4]);
+    assert_eq!(request.focused_clause, 0);
+    assert_eq!(texts(&engine), ["わたしの", "名前"]);
+    assert_eq!(surfaces(&engine), ["わたしの", "ワタシノ"]);
+
+    // clauses before the focused one are kept as they are
+    client.push_response(clause_candidates(vec![
+        Candidate::new("名前", "なまえ"),
+        Candidate::new("なまえ", "なまえ"),
+    ]));
+    press(&mut engine, &client, Key::Right);
+    press(&mut engine, &client, Key::Space);
+    client.push_response(response(
+        &[("わたしの", "わたしの"), ("なま", "生"), ("え", "絵")],
+        1,
+        &["生", "なま"],
+    ));
+    press(
+        &mut engine,
+        &client,
+        KeyEvent::from(Key::Left).with_modifiers(Modifiers::SHIFT),
+    );
+    assert_eq!(last_request(&client).clause_lengths, [4, 2]);
+    assert_eq!(texts(&engine), ["わたしの", "生", "絵"]);
+    assert_eq!(engine.focused_clause(), Some(1));
+}
+
+#[test]
+fn clauses_cannot_grow_past_the_end() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+    client.push_response(clause_candidates(vec![Candidate::new("の", "の")]));
+    press(&mut engine, &client, Key::Right);
+    press(&mut engine, &client, Key::Right);
+    client.clear_requests();
+
+    press(
+        &mut engine,
+        &client,
+        KeyEvent::from(Key::Right).with_modifiers(Modifiers::SHIFT),
+    );
+    press(&mut engine, &client, Key::Right);
+    assert!(client.requests().is_empty());
+    assert_eq!(engine.focused_clause(), Some(2));
+}
+
+#[test]
+fn a_candidate_for_the_start_of_a_clause_keeps_the_rest_as_reading() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+    client.push_response(clause_candidates(vec![Candidate::new("の", "の")]));
+    client.push_response(clause_candidates(vec![
+        Candidate::new("名前", "なまえ"),
+        Candidate::new("生", "なま").with_annotation("名詞"),
+    ]));
+    press(&mut engine, &client, Key::Right);
+    press(&mut engine, &client, Key::Right);
+    press(&mut engine, &client, Key::Space);
+    assert_eq!(engine.candidates()[1].annotation, "名詞");
+    assert_eq!(texts(&engine), ["私", "の", "生え"]);
+}
+
+#[test]
+fn candidates_without_a_consumed_length_cover_the_whole_clause() {
+    let clause = engine::composition::Clause {
+        reading: "なまえ".to_string(),
+        surface: "名前".to_string(),
+        candidates: CandidateList::new(vec![Candidate {
+            surface: "名前".to_string(),
+            reading: "なまえ".to_string(),
+            ..Default::default()
+        }]),
+    };
+    assert_eq!(clause.text(), "名前");
+}
+
+#[test]
+fn every_clause_is_learned_at_commit_even_if_learning_fails() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+    // choose 渡し for the first clause, without telling the server yet
+    press(&mut engine, &client, Key::Space);
+    press(&mut engine, &client, Key::Char('2'));
+    assert!(client.requests().is_empty());
+
+    client.push_select_error(Error::Timeout);
+    let outcome = engine.handle_key(&client, Key::Enter.into()).unwrap();
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::CommitText("渡しの名前".to_string()),
+            Action::HideCandidates
+        ]
+    );
+    assert_eq!(engine.state(), State::Idle);
+    // clauses without candidates report what the server chose
+    let select = |index, surface, reading| {
+        request::Payload::SelectCandidate(SelectCandidateRequest {
+            selected_candidate_index: index,
+            candidate: Some(Candidate::new(surface, reading)),
+        })
+    };
+    assert_eq!(
+        client.requests(),
+        vec![
+            select(1, "渡し", "わたし"),
+            select(0, "の", "の"),
+            select(0, "名前", "なまえ"),
+        ]
+    );
+}
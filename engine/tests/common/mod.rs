@@ -0,0 +1,24 @@
+// helpers shared by the integration tests, each test binary uses only some of them
+#![allow(dead_code)]
+
+use engine::composition::{Action, CompositionEngine};
+use engine::key::{Key, KeyEvent};
+use ipc::client::MockClient;
+
+// types each character as its own key
+pub fn type_keys(engine: &mut CompositionEngine, client: &MockClient, keys: &str) {
+    for c in keys.chars() {
+        engine.handle_key(client, Key::Char(c).into()).unwrap();
+    }
+}
+
+// presses one key the engine must handle, and returns what it did
+pub fn press(
+    engine: &mut CompositionEngine,
+    client: &MockClient,
+    event: impl Into<KeyEvent>,
+) -> Vec<Action> {
+    let outcome = engine.handle_key(client, event.into()).unwrap();
+    assert!(outcome.handled);
+    outcome.actions
+}
@@ -0,0 +1,76 @@
+use engine::compartment::{
+    Compartments, CONVERSION_FULLSHAPE, CONVERSION_KATAKANA, CONVERSION_NATIVE, CONVERSION_ROMAN,
+};
+use engine::composition::InputMethod;
+use engine::mode::InputMode;
+
+#[test]
+fn modes_round_trip_through_the_compartments() {
+    for mode in InputMode::ALL {
+        for input_method in [InputMethod::Romaji, InputMethod::Kana] {
+            let compartments = Compartments::new(mode, InputMode::Hiragana, input_method);
+            assert_eq!(compartments.mode(), mode, "{:?}", compartments);
+        }
+    }
+}
+
+#[test]
+fn direct_mode_closes_the_keyboard_and_keeps_the_on_mode() {
+    let compartments = Compartments::new(InputMode::Direct, InputMode::Katakana, InputMethod::Kana);
+    assert!(!compartments.open);
+    assert_eq!(
+        compartments.conversion,
+        CONVERSION_NATIVE | CONVERSION_KATAKANA | CONVERSION_FULLSHAPE
+    );
+
+    let compartments = Compartments::new(
+        InputMode::Hiragana,
+        InputMode::Hiragana,
+        InputMethod::Romaji,
+    );
+    assert!(compartments.open);
+    assert_eq!(
+        compartments.conversion,
+        CONVERSION_NATIVE | CONVERSION_FULLSHAPE | CONVERSION_ROMAN
+    );
+}
+
+#[test]
+fn reads_modes_written_by_other_programs() {
+    let table = [
+        // open, conversion, expected
+        (false, CONVERSION_NATIVE, InputMode::Direct),
+        (true, 0, InputMode::Direct),
+        (true, CONVERSION_NATIVE, InputMode::Hiragana),
+        (
+            true,
+            CONVERSION_NATIVE | CONVERSION_FULLSHAPE | CONVERSION_ROMAN,
+            InputMode::Hiragana,
+        ),
+        (
+            true,
+            CONVERSION_NATIVE | CONVERSION_KATAKANA,
+            InputMode::HalfWidthKatakana,
+        ),
+        (true, CONVERSION_FULLSHAPE, InputMode::FullWidthAlphanumeric),
+    ];
+    for (open, conversion, expected) in table {
+        assert_eq!(Compartments { open, conversion }.mode(), expected);
+    }
+}
+
+#[test]
+fn closed_compartments_keep_the_on_mode() {
+    let compartments = Compartments {
+        open: false,
+        conversion: CONVERSION_NATIVE | CONVERSION_KATAKANA | CONVERSION_FULLSHAPE,
+    };
+    assert_eq!(compartments.mode(), InputMode::Direct);
+    assert_eq!(compartments.on_mode(), Some(InputMode::Katakana));
+
+    let compartments = Compartments {
+        open: false,
+        conversion: 0,
+    };
+    assert_eq!(compartments.on_mode(), None);
+}
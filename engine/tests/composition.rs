@@ -0,0 +1,461 @@
+mod common;
+
+use engine::candidate::CandidateList;
+use engine::composition::{Action, CompositionEngine, State};
+use engine::key::{Key, KeyEvent, Modifiers};
+use engine::layout::Layout;
+use engine::preedit::{Attribute, Preedit};
+use ipc::client::MockClient;
+use ipc::error::Error;
+use ipc::ipc_proto::{
+    request, Candidate, ConversionRequest, ConversionResponse, SelectCandidateRequest,
+};
+
+use common::type_keys;
+
+fn response(reading: &str, candidates: &[&str]) -> ConversionResponse {
+    ConversionResponse {
+        converted_text: reading.to_string(),
+        candidates: candidates
+            .iter()
+            .map(|surface| Candidate::new(*surface, reading))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn show(reading: &str, candidates: &[&str]) -> Action {
+    Action::ShowCandidates(CandidateList::new(
+        candidates
+            .iter()
+            .map(|surface| Candidate::new(*surface, reading))
+            .collect(),
+    ))
+}
+
+// types "kann" and leaves the engine composing かん
+fn composing(client: &MockClient) -> CompositionEngine {
+    let mut engine = CompositionEngine::new();
+    type_keys(&mut engine, client, "kann");
+    engine
+}
+
+// converts かん and leaves the engine showing 缶
+fn converting(client: &MockClient) -> CompositionEngine {
+    let mut engine = composing(client);
+    client.push_response(response("かん", &["缶", "かん", "カン"]));
+    engine.handle_key(client, Key::Space.into()).unwrap();
+    client.clear_requests();
+    engine
+}
+
+#[test]
+fn typing_starts_a_composition() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+
+    let outcome = engine.handle_key(&client, Key::Char('a').into()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::StartComposition,
+            Action::SetPreedit(Preedit::single("あ", Attribute::Input)),
+            Action::HideCandidates,
+        ]
+    );
+    assert_eq!(engine.state(), State::Composing);
+}
+
+#[test]
+fn the_reading_is_composed_without_the_server() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+
+    let mut readings = Vec::new();
+    for c in "kyotto".chars() {
+        engine.handle_key(&client, Key::Char(c).into()).unwrap();
+        readings.push(engine.preedit().text());
+    }
+    assert_eq!(
+        readings,
+        ["k", "ky", "きょ", "きょt", "きょっt", "きょっと"]
+    );
+
+    // Shift types uppercase letters as they are
+    let event = KeyEvent::from_message(0x41, 0x001E_0001).with_modifiers(Modifiers::SHIFT);
+    engine.handle_key(&client, event).unwrap();
+    assert_eq!(engine.reading(), "きょっとA");
+
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn symbols_are_resolved_with_the_keyboard_layout() {
+    let client = MockClient::new();
+
+    // Shift+2 is @ on a US keyboard, and starts a composition like a letter
+    let mut engine = CompositionEngine::new().with_layout(Layout::Us);
+    let event = KeyEvent::new(0x32).with_modifiers(Modifiers::SHIFT);
+    let outcome = engine.handle_key(&client, event.clone()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(engine.reading(), "@");
+
+    // the same key is " on a JIS keyboard, and [ becomes a bracket
+    engine.reset();
+    engine.set_layout(Layout::Jis);
+    engine.handle_key(&client, event).unwrap();
+    engine.handle_key(&client, KeyEvent::new(0xDB)).unwrap();
+    assert_eq!(engine.reading(), "\"「");
+}
+
+#[test]
+fn keys_that_produce_no_input_pass_through() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+
+    for key in [Key::Enter, Key::Space, Key::Other(0x70)] {
+        let outcome = engine.handle_key(&client, key.into()).unwrap();
+        assert!(!outcome.handled);
+        assert!(outcome.actions.is_empty());
+    }
+    let shortcut = KeyEvent::from(Key::Char('c')).with_modifiers(Modifiers::CTRL);
+    assert!(!engine.handle_key(&client, shortcut).unwrap().handled);
+    assert_eq!(engine.state(), State::Idle);
+    // not even asked
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn space_converts_and_cycles_candidates() {
+    let client = MockClient::new();
+    let mut engine = composing(&client);
+    client.push_response(response("かん", &["缶", "かん", "カン"]));
+
+    let outcome = engine.handle_key(&client, Key::Space.into()).unwrap();
+    assert_eq!(engine.state(), State::Converting);
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::SetPreedit(Preedit::single("缶", Attribute::Focused)),
+            show("かん", &["缶", "かん", "カン"]),
+            Action::MoveCandidates,
+        ]
+    );
+    assert_eq!(
+        client.requests(),
+        vec![request::Payload::Convert(ConversionRequest::from_reading(
+            "かん"
+        ))]
+    );
+    client.clear_requests();
+
+    engine.handle_key(&client, Key::Space.into()).unwrap();
+    assert_eq!(engine.state(), State::CandidateSelecting);
+    assert_eq!(engine.selected(), Some(1));
+
+    engine.handle_key(&client, Key::Down.into()).unwrap();
+    engine.handle_key(&client, Key::Down.into()).unwrap();
+    assert_eq!(engine.selected(), Some(0));
+
+    engine.handle_key(&client, Key::Up.into()).unwrap();
+    assert_eq!(engine.selected(), Some(2));
+    assert_eq!(engine.preedit().text(), "カン");
+
+    // moving around doesn't need the server
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn space_finishes_a_trailing_n() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    type_keys(&mut engine, &client, "kan");
+    assert_eq!(engine.reading(), "かn");
+
+    client.push_response(response("かん", &["缶"]));
+    engine.handle_key(&client, Key::Space.into()).unwrap();
+    assert_eq!(
+        client.requests(),
+        vec![request::Payload::Convert(ConversionRequest::from_reading(
+            "かん"
+        ))]
+    );
+}
+
+#[test]
+fn space_without_candidates_keeps_the_reading() {
+    let client = MockClient::new();
+    let mut engine = composing(&client);
+
+    client.push_response(response("かん", &[]));
+    let outcome = engine.handle_key(&client, Key::Space.into()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(engine.state(), State::Composing);
+    assert_eq!(
+        outcome.actions[0],
+        Action::SetPreedit(Preedit::single("かん", Attribute::Input))
+    );
+}
+
+#[test]
+fn enter_commits_the_selected_candidate() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+    engine.handle_key(&client, Key::Space.into()).unwrap();
+
+    let outcome = engine.handle_key(&client, Key::Enter.into()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::CommitText("かん".to_string()),
+            Action::HideCandidates
+        ]
+    );
+    assert_eq!(engine.state(), State::Idle);
+    assert_eq!(
+        client.requests(),
+        vec![request::Payload::SelectCandidate(SelectCandidateRequest {
+            selected_candidate_index: 1,
+            candidate: Some(Candidate::new("かん", "かん")),
+        })]
+    );
+}
+
+#[test]
+fn typing_while_converting_commits_and_starts_over() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+
+    let outcome = engine.handle_key(&client, Key::Char('a').into()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(
+        outcome.actions[..3],
+        [
+            Action::CommitText("缶".to_string()),
+            Action::HideCandidates,
+            Action::StartComposition,
+        ]
+    );
+    assert_eq!(engine.state(), State::Composing);
+    assert_eq!(engine.reading(), "あ");
+}
+
+#[test]
+fn escape_goes_back_to_the_reading() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+
+    let outcome = engine.handle_key(&client, Key::Escape.into()).unwrap();
+    assert_eq!(engine.state(), State::Composing);
+    assert_eq!(
+        outcome.actions[0],
+        Action::SetPreedit(Preedit::single("かん", Attribute::Input))
+    );
+}
+
+#[test]
+fn backspace_edits_the_reading() {
+    let client = MockClient::new();
+    let mut engine = composing(&client);
+
+    let outcome = engine.handle_key(&client, Key::Backspace.into()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(
+        outcome.actions[0],
+        Action::SetPreedit(Preedit::single("か", Attribute::Input))
+    );
+    assert_eq!(engine.state(), State::Composing);
+
+    // pending letters go one at a time
+    type_keys(&mut engine, &client, "ky");
+    engine.handle_key(&client, Key::Backspace.into()).unwrap();
+    assert_eq!(engine.reading(), "かk");
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn composition_ends_when_the_reading_is_empty() {
+    let client = MockClient::new();
+    let mut engine = composing(&client);
+
+    engine.handle_key(&client, Key::Backspace.into()).unwrap();
+    let outcome = engine.handle_key(&client, Key::Backspace.into()).unwrap();
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::SetPreedit(Preedit::default()),
+            Action::EndComposition,
+            Action::HideCandidates
+        ]
+    );
+    assert_eq!(engine.state(), State::Idle);
+}
+
+#[test]
+fn enter_commits_the_reading() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    type_keys(&mut engine, &client, "kan");
+
+    let outcome = engine.handle_key(&client, Key::Enter.into()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::CommitText("かん".to_string()),
+            Action::HideCandidates
+        ]
+    );
+    assert_eq!(engine.state(), State::Idle);
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn cursor_keys_commit_the_reading_and_pass_through() {
+    for key in [Key::Left, Key::Right, Key::Home, Key::End, Key::Delete] {
+        let client = MockClient::new();
+        let mut engine = CompositionEngine::new();
+        type_keys(&mut engine, &client, "kan");
+
+        // the app moves its caret (or deletes) after the reading is committed
+        let outcome = engine.handle_key(&client, key.into()).unwrap();
+        assert!(!outcome.handled, "{:?}", key);
+        assert_eq!(
+            outcome.actions,
+            vec![
+                Action::CommitText("かん".to_string()),
+                Action::HideCandidates
+            ]
+        );
+        assert_eq!(engine.state(), State::Idle);
+    }
+}
+
+#[test]
+fn escape_cancels_the_composition() {
+    let client = MockClient::new();
+    let mut engine = composing(&client);
+
+    let outcome = engine.handle_key(&client, Key::Escape.into()).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::SetPreedit(Preedit::default()),
+            Action::EndComposition,
+            Action::HideCandidates
+        ]
+    );
+    assert_eq!(engine.state(), State::Idle);
+    assert!(engine.reading().is_empty());
+}
+
+#[test]
+fn escape_while_converting_then_enter_commits_the_reading() {
+    let client = MockClient::new();
+    let mut engine = converting(&client);
+    engine.handle_key(&client, Key::Escape.into()).unwrap();
+
+    let outcome = engine.handle_key(&client, Key::Enter.into()).unwrap();
+    assert_eq!(outcome.actions[0], Action::CommitText("かん".to_string()));
+    // no candidate was chosen
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn failed_conversion_keeps_the_reading() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    type_keys(&mut engine, &client, "kan");
+
+    client.push_error(Error::Disconnected);
+    assert!(engine.handle_key(&client, Key::Space.into()).is_err());
+    assert_eq!(engine.state(), State::Composing);
+    // not even the trailing n is finished yet
+    assert_eq!(engine.reading(), "かn");
+}
+
+#[test]
+fn errors_leave_the_composition_alone() {
+    let client = MockClient::new();
+    let mut engine = composing(&client);
+
+    client.push_error(Error::Timeout);
+    assert!(matches!(
+        engine.handle_key(&client, Key::Space.into()),
+        Err(Error::Timeout)
+    ));
+    assert_eq!(engine.state(), State::Composing);
+    assert_eq!(engine.reading(), "かん");
+
+    assert_eq!(
+        engine.reset(),
+        vec![Action::EndComposition, Action::HideCandidates]
+    );
+    assert_eq!(engine.state(), State::Idle);
+    assert!(engine.reset().is_empty());
+}
+
+#[test]
+fn virtual_keys_round_trip() {
+    for virtual_key in [
+        0x08, 0x0D, 0x1B, 0x20, 0x25, 0x28, 0x30, 0x41, 0x5A, 0xBD, 0x70,
+    ] {
+        assert_eq!(
+            Key::from_virtual_key(virtual_key).virtual_key(),
+            virtual_key
+        );
+    }
+    assert_eq!(Key::from_virtual_key(0x41), Key::Char('a'));
+    assert_eq!(Key::from_virtual_key(0xBD), Key::Char('-'));
+}
+
+#[test]
+fn page_keys_and_numbers_choose_candidates() {
+    let client = MockClient::new();
+    let mut engine = composing(&client);
+    let candidates: Vec<String> = (0..12).map(|i| format!("候補{}", i)).collect();
+    let candidates: Vec<&str> = candidates.iter().map(String::as_str).collect();
+    client.push_response(response("かん", &candidates));
+    engine.handle_key(&client, Key::Space.into()).unwrap();
+
+    let outcome = engine.handle_key(&client, Key::PageDown.into()).unwrap();
+    assert_eq!(engine.state(), State::CandidateSelecting);
+    assert_eq!(engine.selected(), Some(9));
+    match &outcome.actions[1] {
+        Action::ShowCandidates(list) => {
+            assert_eq!(list.page(), 1);
+            assert_eq!(list.page_candidates().len(), 3);
+        }
+        other => panic!("expected the candidate window, got {:?}", other),
+    }
+
+    // 2 on this page is the 11th candidate, and the window closes
+    client.clear_requests();
+    let outcome = engine.handle_key(&client, Key::Char('2').into()).unwrap();
+    assert_eq!(engine.state(), State::Converting);
+    assert_eq!(engine.selected(), Some(10));
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::SetPreedit(Preedit::single("候補10", Attribute::Focused)),
+            Action::HideCandidates,
+        ]
+    );
+    // picking only changes the selection, the server learns it at commit
+    assert!(client.requests().is_empty());
+
+    // without the window, numbers are typed as usual
+    let outcome = engine.handle_key(&client, Key::Char('2').into()).unwrap();
+    assert_eq!(outcome.actions[0], Action::CommitText("候補10".to_string()));
+    assert_eq!(engine.state(), State::Composing);
+    assert_eq!(
+        client.requests()[0],
+        request::Payload::SelectCandidate(SelectCandidateRequest {
+            selected_candidate_index: 10,
+            candidate: Some(Candidate::new("候補10", "かん")),
+        })
+    );
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use engine::composition::{CompositionEngine, InputMethod};
+use engine::kana::{combine, kana, DAKUTEN, HANDAKUTEN};
+use engine::key::{Key, KeyEvent, Modifiers};
+use ipc::client::MockClient;
+
+use common::press;
+
+#[test]
+fn maps_the_jis_kana_layout() {
+    let table = [
+        (0x31, Modifiers::NONE, Some('ぬ')),
+        (0x33, Modifiers::NONE, Some('あ')),
+        (0x33, Modifiers::SHIFT, Some('ぁ')),
+        (0x30, Modifiers::SHIFT, Some('を')),
+        (0x5A, Modifiers::SHIFT, Some('っ')),
+        (0x54, Modifiers::NONE, Some('か')),
+        (0xC0, Modifiers::NONE, Some(DAKUTEN)),
+        (0xDB, Modifiers::NONE, Some(HANDAKUTEN)),
+        (0xDB, Modifiers::SHIFT, Some('「')),
+        (0xBC, Modifiers::SHIFT, Some('、')),
+        (0xDC, Modifiers::NONE, Some('ー')),
+        (0xE2, Modifiers::NONE, Some('ろ')),
+        (0x54, Modifiers::CTRL, None),
+        (0x20, Modifiers::NONE, None),
+        (0x70, Modifiers::NONE, None),
+    ];
+
+    for (virtual_key, modifiers, expected) in table {
+        assert_eq!(
+            kana(&KeyEvent::new(virtual_key).with_modifiers(modifiers)),
+            expected,
+            "vk {:#04x} with {:?}",
+            virtual_key,
+            modifiers
+        );
+    }
+    assert_eq!(kana(&KeyEvent::from_message(0xC1, 0x0073_0001)), Some('ろ'));
+}
+
+#[test]
+fn combines_voiced_marks() {
+    assert_eq!(combine('か', DAKUTEN), Some('が'));
+    assert_eq!(combine('つ', DAKUTEN), Some('づ'));
+    assert_eq!(combine('ほ', DAKUTEN), Some('ぼ'));
+    assert_eq!(combine('う', DAKUTEN), Some('ゔ'));
+    assert_eq!(combine('は', HANDAKUTEN), Some('ぱ'));
+    assert_eq!(combine('か', HANDAKUTEN), None);
+    assert_eq!(combine('あ', DAKUTEN), None);
+}
+
+#[test]
+fn kana_input_types_kana_directly() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    engine.set_input_method(InputMethod::Kana);
+
+    // か ゛ ふ ゜ ぁ
+    press(&mut engine, &client, KeyEvent::new(0x54));
+    press(&mut engine, &client, KeyEvent::new(0xC0));
+    press(&mut engine, &client, KeyEvent::new(0x32));
+    press(&mut engine, &client, KeyEvent::new(0xDB));
+    press(
+        &mut engine,
+        &client,
+        KeyEvent::new(0x33).with_modifiers(Modifiers::SHIFT),
+    );
+    assert_eq!(engine.reading(), "がぷぁ");
+
+    // marks that can't be combined stay on their own
+    press(&mut engine, &client, KeyEvent::new(0xC0));
+    assert_eq!(engine.reading(), "がぷぁ゛");
+
+    // the numpad still types digits
+    press(&mut engine, &client, KeyEvent::new(0x61));
+    assert_eq!(engine.reading(), "がぷぁ゛1");
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn switching_input_methods_finishes_pending_romaji() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    for c in "kan".chars() {
+        engine.handle_key(&client, Key::Char(c).into()).unwrap();
+    }
+
+    engine.set_input_method(InputMethod::Kana);
+    press(&mut engine, &client, KeyEvent::new(0x55));
+    assert_eq!(engine.reading(), "かんな");
+
+    engine.set_input_method(InputMethod::Romaji);
+    engine.handle_key(&client, Key::Char('a').into()).unwrap();
+    assert_eq!(engine.reading(), "かんなあ");
+}
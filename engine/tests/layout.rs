@@ -0,0 +1,86 @@
+use engine::key::{KeyEvent, Modifiers};
+use engine::layout::Layout;
+use ipc::ipc_proto::Toggles;
+
+fn character(layout: Layout, virtual_key: u32, modifiers: Modifiers) -> Option<char> {
+    layout.character(&KeyEvent::new(virtual_key).with_modifiers(modifiers))
+}
+
+#[test]
+fn symbol_keys_depend_on_the_layout() {
+    let table = [
+        // vk, modifiers, jis, us
+        (0xC0, Modifiers::NONE, Some('@'), Some('`')),
+        (0xC0, Modifiers::SHIFT, Some('`'), Some('~')),
+        (0xBA, Modifiers::NONE, Some(':'), Some(';')),
+        (0xBA, Modifiers::SHIFT, Some('*'), Some(':')),
+        (0xBB, Modifiers::NONE, Some(';'), Some('=')),
+        (0xBD, Modifiers::NONE, Some('-'), Some('-')),
+        (0xBD, Modifiers::SHIFT, Some('='), Some('_')),
+        (0xDB, Modifiers::NONE, Some('['), Some('[')),
+        (0xDE, Modifiers::NONE, Some('^'), Some('\'')),
+        (0xDE, Modifiers::SHIFT, Some('~'), Some('"')),
+        (0xE2, Modifiers::NONE, Some('\\'), None),
+        (0xE2, Modifiers::SHIFT, Some('_'), None),
+        (0x32, Modifiers::NONE, Some('2'), Some('2')),
+        (0x32, Modifiers::SHIFT, Some('"'), Some('@')),
+        (0x36, Modifiers::SHIFT, Some('&'), Some('^')),
+        (0x30, Modifiers::SHIFT, None, Some(')')),
+    ];
+
+    for (virtual_key, modifiers, jis, us) in table {
+        assert_eq!(
+            character(Layout::Jis, virtual_key, modifiers),
+            jis,
+            "jis vk {:#04x} with {:?}",
+            virtual_key,
+            modifiers
+        );
+        assert_eq!(
+            character(Layout::Us, virtual_key, modifiers),
+            us,
+            "us vk {:#04x} with {:?}",
+            virtual_key,
+            modifiers
+        );
+    }
+}
+
+#[test]
+fn letters_numpad_and_space_are_the_same_everywhere() {
+    for layout in [Layout::Jis, Layout::Us] {
+        assert_eq!(character(layout, 0x41, Modifiers::NONE), Some('a'));
+        assert_eq!(character(layout, 0x41, Modifiers::SHIFT), Some('A'));
+        let caps_lock = Toggles {
+            caps_lock: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            layout.character(&KeyEvent::new(0x41).with_toggles(caps_lock)),
+            Some('A')
+        );
+        assert_eq!(character(layout, 0x67, Modifiers::NONE), Some('7'));
+        assert_eq!(character(layout, 0x6B, Modifiers::NONE), Some('+'));
+        assert_eq!(character(layout, 0x20, Modifiers::NONE), Some(' '));
+        // no characters for shortcuts or keys that do not type
+        assert_eq!(character(layout, 0x41, Modifiers::CTRL), None);
+        assert_eq!(character(layout, 0xC0, Modifiers::ALT), None);
+        assert_eq!(character(layout, 0x0D, Modifiers::NONE), None);
+        assert_eq!(character(layout, 0x70, Modifiers::NONE), None);
+    }
+}
+
+#[test]
+fn the_ro_key_is_found_by_its_scan_code() {
+    let event = KeyEvent::from_message(0xC1, 0x0073_0001);
+    assert_eq!(Layout::Jis.character(&event), Some('\\'));
+    assert_eq!(Layout::Us.character(&event), None);
+}
+
+#[test]
+fn layouts_are_parsed_from_their_names() {
+    assert_eq!("jis".parse(), Ok(Layout::Jis));
+    assert_eq!("US".parse(), Ok(Layout::Us));
+    assert_eq!("109".parse(), Ok(Layout::Jis));
+    assert!("dvorak".parse::<Layout>().is_err());
+}
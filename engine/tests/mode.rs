@@ -0,0 +1,192 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use engine::composition::{Action, CompositionEngine, State};
+use engine::key::{Key, KeyEvent, Modifiers};
+use engine::mode::{switch, InputMode};
+use engine::preedit::{Attribute, Preedit};
+use engine::transform::{to_full_width, to_half_width_katakana, to_katakana};
+use ipc::client::MockClient;
+
+use common::type_keys;
+
+#[test]
+fn switching_keys() {
+    let shift = Modifiers::SHIFT;
+    let table = [
+        // vk, modifiers, current mode, expected
+        (
+            0x19,
+            Modifiers::NONE,
+            InputMode::Hiragana,
+            Some(InputMode::Direct),
+        ),
+        (
+            0xF3,
+            Modifiers::NONE,
+            InputMode::Direct,
+            Some(InputMode::Katakana),
+        ),
+        (
+            0xF4,
+            Modifiers::NONE,
+            InputMode::Katakana,
+            Some(InputMode::Direct),
+        ),
+        (
+            0x16,
+            Modifiers::NONE,
+            InputMode::Direct,
+            Some(InputMode::Katakana),
+        ),
+        (
+            0x1A,
+            Modifiers::NONE,
+            InputMode::Hiragana,
+            Some(InputMode::Direct),
+        ),
+        (
+            0xF2,
+            Modifiers::NONE,
+            InputMode::Direct,
+            Some(InputMode::Hiragana),
+        ),
+        (0xF2, shift, InputMode::Hiragana, Some(InputMode::Katakana)),
+        (
+            0x15,
+            Modifiers::NONE,
+            InputMode::Katakana,
+            Some(InputMode::Hiragana),
+        ),
+        (
+            0xF1,
+            Modifiers::NONE,
+            InputMode::Hiragana,
+            Some(InputMode::Katakana),
+        ),
+        (
+            0xF0,
+            Modifiers::NONE,
+            InputMode::Hiragana,
+            Some(InputMode::Direct),
+        ),
+        (
+            0xF0,
+            shift,
+            InputMode::Hiragana,
+            Some(InputMode::FullWidthAlphanumeric),
+        ),
+        (0x41, Modifiers::NONE, InputMode::Hiragana, None),
+        (0x20, shift, InputMode::Direct, None),
+    ];
+
+    for (virtual_key, modifiers, mode, expected) in table {
+        let event = KeyEvent::new(virtual_key).with_modifiers(modifiers);
+        // the IME turns back on in katakana
+        assert_eq!(
+            switch(&event, mode, InputMode::Katakana),
+            expected,
+            "vk {:#04x} with {:?} in {:?}",
+            virtual_key,
+            modifiers,
+            mode
+        );
+    }
+}
+
+#[test]
+fn transforms_the_reading() {
+    assert_eq!(to_katakana("きょうはいいてんき"), "キョウハイイテンキ");
+    assert_eq!(to_half_width_katakana("がっこう"), "ｶﾞｯｺｳ");
+    assert_eq!(to_half_width_katakana("ぱーてぃー、ゔ"), "ﾊﾟｰﾃｨｰ､ｳﾞ");
+    assert_eq!(to_half_width_katakana("ａｂｃ"), "abc");
+    assert_eq!(to_full_width("Abc 1!"), "Ａｂｃ　１！");
+    assert_eq!(InputMode::Katakana.transform("かk"), "カk");
+    assert_eq!(InputMode::Hiragana.transform("かk"), "かk");
+}
+
+#[test]
+fn the_preedit_follows_the_mode() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    engine.set_mode(InputMode::Katakana);
+    type_keys(&mut engine, &client, "ga");
+    assert_eq!(engine.preedit().text(), "ガ");
+
+    // switching while composing shows the reading again
+    assert_eq!(
+        engine.set_mode(InputMode::HalfWidthKatakana),
+        vec![Action::SetPreedit(Preedit::single("ｶﾞ", Attribute::Input))]
+    );
+    let outcome = engine.handle_key(&client, Key::Enter.into()).unwrap();
+    assert_eq!(outcome.actions[0], Action::CommitText("ｶﾞ".to_string()));
+}
+
+#[test]
+fn full_width_alphanumeric_skips_romaji() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    engine.set_mode(InputMode::FullWidthAlphanumeric);
+    type_keys(&mut engine, &client, "ka1");
+    assert_eq!(engine.preedit().text(), "ｋａ１");
+}
+
+#[test]
+fn direct_mode_passes_keys_through() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    engine.set_mode(InputMode::Direct);
+
+    let event = KeyEvent::from(Key::Char('a'));
+    assert!(!engine.wants_key(&event));
+    let outcome = engine.handle_key(&client, event).unwrap();
+    assert!(!outcome.handled);
+    assert_eq!(engine.state(), State::Idle);
+
+    // Hankaku/Zenkaku is still ours
+    assert!(engine.wants_key(&KeyEvent::new(0x19)));
+}
+
+#[test]
+fn switching_keys_commit_the_composition_and_remember_the_mode() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    engine.set_mode(InputMode::Katakana);
+    type_keys(&mut engine, &client, "kan");
+
+    // Hankaku/Zenkaku turns the IME off and commits what was typed
+    let outcome = engine.handle_key(&client, KeyEvent::new(0x19)).unwrap();
+    assert!(outcome.handled);
+    assert_eq!(
+        outcome.actions,
+        vec![
+            Action::CommitText("カン".to_string()),
+            Action::HideCandidates,
+            Action::SetMode(InputMode::Direct),
+        ]
+    );
+    assert_eq!(engine.state(), State::Idle);
+
+    // and turns it back on in katakana
+    let outcome = engine.handle_key(&client, KeyEvent::new(0x19)).unwrap();
+    assert_eq!(outcome.actions, vec![Action::SetMode(InputMode::Katakana)]);
+    assert_eq!(engine.mode(), InputMode::Katakana);
+}
+
+#[test]
+fn the_on_mode_can_be_shared() {
+    let client = MockClient::new();
+    let on_mode = Rc::new(Cell::new(InputMode::Hiragana));
+    let mut engine = CompositionEngine::new().with_on_mode(Rc::clone(&on_mode));
+    engine.set_mode(InputMode::Direct);
+
+    // the language bar or another program changed it while the IME was off
+    on_mode.set(InputMode::Katakana);
+    let outcome = engine.handle_key(&client, KeyEvent::new(0x19)).unwrap();
+    assert_eq!(outcome.actions, vec![Action::SetMode(InputMode::Katakana)]);
+
+    engine.set_mode(InputMode::HalfWidthKatakana);
+    assert_eq!(on_mode.get(), InputMode::HalfWidthKatakana);
+}
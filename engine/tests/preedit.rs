@@ -0,0 +1,42 @@
+use engine::preedit::{Attribute, Preedit, Segment};
+
+fn segment(text: &str, attribute: Attribute) -> Segment {
+    Segment {
+        text: text.to_string(),
+        attribute,
+    }
+}
+
+#[test]
+fn ranges_are_counted_in_utf16() {
+    let preedit = Preedit {
+        segments: vec![
+            segment("𠮷野家", Attribute::Converted),
+            segment("", Attribute::Converted),
+            segment("で", Attribute::Focused),
+            segment("たべる", Attribute::Input),
+        ],
+        caret: 4,
+    };
+    assert_eq!(preedit.text(), "𠮷野家でたべる");
+    assert_eq!(
+        preedit.utf16_ranges(),
+        vec![
+            (0..4, Attribute::Converted),
+            (4..5, Attribute::Focused),
+            (5..8, Attribute::Input),
+        ]
+    );
+    assert_eq!(preedit.utf16_caret(), 5);
+}
+
+#[test]
+fn the_caret_stays_inside_the_text() {
+    let mut preedit = Preedit::single("かな", Attribute::Input);
+    assert_eq!(preedit.caret, 2);
+    assert_eq!(preedit.utf16_caret(), 2);
+
+    preedit.caret = 10;
+    assert_eq!(preedit.utf16_caret(), 2);
+    assert!(Preedit::default().utf16_ranges().is_empty());
+}
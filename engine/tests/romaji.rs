@@ -0,0 +1,153 @@
+use engine::romaji::{RomajiComposer, RomajiTable, Rule, TableError};
+
+fn compose(input: &str) -> String {
+    let mut composer = RomajiComposer::default();
+    composer.push_str(input);
+    composer.text()
+}
+
+#[test]
+fn converts_with_the_builtin_table() {
+    let table = [
+        ("aiueo", "あいうえお"),
+        ("kyouha", "きょうは"),
+        ("shinbun", "しんぶn"),
+        ("shinbunn", "しんぶん"),
+        ("konnnichiha", "こんにちは"),
+        ("kanji", "かんじ"),
+        ("nyuuryoku", "にゅうりょく"),
+        // sokuon from doubled consonants
+        ("kitte", "きって"),
+        ("matcha", "まっちゃ"),
+        ("tt", "っt"),
+        // small kana
+        ("xtu", "っ"),
+        ("ltu", "っ"),
+        ("xya", "ゃ"),
+        ("fa", "ふぁ"),
+        ("-", "ー"),
+        ("ra-menn", "らーめん"),
+        ("a,b.", "あ、b。"),
+        // pending input stays as it is
+        ("k", "k"),
+        ("ky", "ky"),
+        ("n", "n"),
+        ("@", "@"),
+        ("Tokyo", "Tおきょ"),
+    ];
+
+    for (input, expected) in table {
+        assert_eq!(compose(input), expected, "{}", input);
+    }
+}
+
+#[test]
+fn pending_input_is_finished_on_flush() {
+    let mut composer = RomajiComposer::default();
+    composer.push_str("kan");
+    assert_eq!(composer.pending(), "n");
+    // what flush would give, without flushing yet
+    assert_eq!(composer.flushed_text(), "かん");
+    assert_eq!(composer.pending(), "n");
+
+    composer.flush();
+    assert_eq!(composer.text(), "かん");
+    assert_eq!(composer.pending(), "");
+
+    // letters that never become kana are kept
+    composer.push_str("ky");
+    assert_eq!(composer.flushed_text(), "かんky");
+    composer.flush();
+    assert_eq!(composer.text(), "かんky");
+}
+
+#[test]
+fn backspace_removes_pending_letters_first() {
+    let mut composer = RomajiComposer::default();
+    composer.push_str("kaky");
+    composer.backspace();
+    assert_eq!(composer.text(), "かk");
+    composer.backspace();
+    composer.backspace();
+    assert!(composer.is_empty());
+    composer.backspace();
+    assert!(composer.is_empty());
+}
+
+#[test]
+fn keeps_the_keys_that_made_each_kana() {
+    let mut composer = RomajiComposer::default();
+    composer.push_str("kittenky");
+    assert_eq!(composer.text(), "きってんky");
+    assert_eq!(composer.raw(), "kittenky");
+
+    // a partly deleted kana has no keys left, so it stands for itself
+    let mut composer = RomajiComposer::default();
+    composer.push_str("kya");
+    composer.backspace();
+    assert_eq!(composer.raw(), "き");
+
+    let mut composer = RomajiComposer::default();
+    composer.push_kana('か');
+    composer.push_kana('゛');
+    assert_eq!(composer.text(), "が");
+    assert_eq!(composer.raw(), "か゛");
+}
+
+#[test]
+fn loads_mozc_style_tables() {
+    let tsv = "# azik\r\nkz\tかん\n\nq\tん\nkk\tっ\tk\nka\tか\n";
+    let table = RomajiTable::parse(tsv).unwrap();
+    assert_eq!(table.len(), 4);
+    assert_eq!(
+        table.get("kk"),
+        Some(&Rule {
+            input: "kk".to_string(),
+            output: "っ".to_string(),
+            next: "k".to_string(),
+        })
+    );
+
+    let mut composer = RomajiComposer::new(table);
+    composer.push_str("kzqkka");
+    assert_eq!(composer.text(), "かんんっか");
+    // rules missing from the user table are not filled in from the builtin one
+    composer.push_str("shi");
+    assert_eq!(composer.text(), "かんんっかshi");
+}
+
+#[test]
+fn later_rules_override_earlier_ones() {
+    let table = RomajiTable::parse("a\tあ\na\tア\n").unwrap();
+    let mut composer = RomajiComposer::new(table);
+    composer.push('a');
+    assert_eq!(composer.text(), "ア");
+}
+
+#[test]
+fn invalid_lines_are_reported() {
+    for (tsv, line) in [
+        ("a\tあ\nka\n", 2),
+        ("\tあ\n", 1),
+        ("a\tあ\n\nk\t\n", 3),
+        ("a\tあ\tb\tc\n", 1),
+    ] {
+        match RomajiTable::parse(tsv) {
+            Err(TableError::InvalidLine { line: actual, .. }) => {
+                assert_eq!(actual, line, "{:?}", tsv)
+            }
+            other => panic!("{:?} parsed as {:?}", tsv, other),
+        }
+    }
+}
+
+#[test]
+fn loads_tables_from_files() {
+    let path = std::env::temp_dir().join(format!("romaji-{}.tsv", std::process::id()));
+    std::fs::write(&path, "a\tア\n").unwrap();
+    let table = RomajiTable::load(&path).unwrap();
+    assert_eq!(table.get("a").unwrap().output, "ア");
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(RomajiTable::load(&path), Err(TableError::Io(_))));
+}
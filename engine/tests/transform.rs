@@ -0,0 +1,130 @@
+mod common;
+
+use engine::composition::{Action, CompositionEngine, State};
+use engine::key::{Key, KeyEvent};
+use engine::mode::InputMode;
+use engine::preedit::{Attribute, Preedit};
+use engine::transform::{
+    to_full_width_katakana, to_half_width, to_half_width_katakana, to_hiragana, Transform,
+};
+use ipc::client::MockClient;
+use ipc::ipc_proto::{Candidate, ConversionResponse};
+
+use common::{press, type_keys};
+
+const F6: u32 = 0x75;
+const F7: u32 = 0x76;
+const F8: u32 = 0x77;
+const F9: u32 = 0x78;
+const F10: u32 = 0x79;
+
+#[test]
+fn converts_scripts_and_widths() {
+    assert_eq!(
+        to_hiragana("キョウハ、ヴァイオリン!"),
+        "きょうは、ゔぁいおりん!"
+    );
+    assert_eq!(to_half_width("Ａｂｃ　１！ー"), "Abc 1!ー");
+
+    let table = [
+        (Transform::Hiragana, "がっこう"),
+        (Transform::Katakana, "ガッコウ"),
+        (Transform::HalfWidthKatakana, "ｶﾞｯｺｳ"),
+        (Transform::FullWidthAlphanumeric, "ｇａｋｋｏｕ"),
+        (Transform::HalfWidthAlphanumeric, "gakkou"),
+    ];
+    for (transform, expected) in table {
+        assert_eq!(transform.apply("がっこう", "gakkou"), expected);
+    }
+}
+
+#[test]
+fn half_width_katakana_becomes_full_width() {
+    assert_eq!(to_full_width_katakana("ｶﾞｯｺｳ"), "ガッコウ");
+    assert_eq!(
+        to_full_width_katakana("ﾊﾟﾋﾟｰ､ｳﾞｧｲｵﾘﾝ｡"),
+        "パピー、ヴァイオリン。"
+    );
+    // marks that can't be merged stay on their own, other text is left alone
+    assert_eq!(to_full_width_katakana("ｱﾞﾟabc"), "ア゛゜abc");
+
+    let text = "ゔぁいおりんとぱぴぷぺぽ";
+    assert_eq!(
+        to_full_width_katakana(&to_half_width_katakana(text)),
+        "ヴァイオリントパピプペポ"
+    );
+    assert_eq!(Transform::Hiragana.apply("ｶﾞｯｺｳ", ""), "がっこう");
+}
+
+#[test]
+fn function_keys_replace_the_preedit() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    type_keys(&mut engine, &client, "toukyoun");
+
+    let table = [
+        (F7, "トウキョウン"),
+        (F8, "ﾄｳｷｮｳﾝ"),
+        (F9, "ｔｏｕｋｙｏｕｎ"),
+        (F10, "toukyoun"),
+        (F6, "とうきょうん"),
+    ];
+    for (virtual_key, expected) in table {
+        assert_eq!(
+            press(&mut engine, &client, Key::Other(virtual_key)),
+            vec![
+                Action::SetPreedit(Preedit::single(expected, Attribute::Input)),
+                Action::HideCandidates,
+            ]
+        );
+    }
+
+    press(&mut engine, &client, Key::Other(F7));
+    let outcome = engine.handle_key(&client, Key::Enter.into()).unwrap();
+    assert_eq!(
+        outcome.actions[0],
+        Action::CommitText("トウキョウン".to_string())
+    );
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn editing_the_reading_drops_the_transform() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    engine.set_mode(InputMode::Katakana);
+    type_keys(&mut engine, &client, "ka");
+
+    press(&mut engine, &client, Key::Other(F6));
+    assert_eq!(engine.preedit().text(), "か");
+    type_keys(&mut engine, &client, "ki");
+    assert_eq!(engine.preedit().text(), "カキ");
+}
+
+#[test]
+fn function_keys_stop_a_conversion() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    type_keys(&mut engine, &client, "kann");
+    client.push_response(ConversionResponse {
+        converted_text: "かん".to_string(),
+        candidates: vec![Candidate::new("缶", "かん")],
+        ..Default::default()
+    });
+    engine.handle_key(&client, Key::Space.into()).unwrap();
+    assert_eq!(engine.state(), State::Converting);
+
+    press(&mut engine, &client, Key::Other(F7));
+    assert_eq!(engine.state(), State::Composing);
+    assert_eq!(engine.preedit().text(), "カン");
+    assert!(engine.candidates().is_empty());
+}
+
+#[test]
+fn function_keys_pass_through_without_a_composition() {
+    let client = MockClient::new();
+    let mut engine = CompositionEngine::new();
+    let event = KeyEvent::from(Key::Other(F7));
+    assert!(!engine.wants_key(&event));
+    assert!(!engine.handle_key(&client, event).unwrap().handled);
+}
@@ -1,8 +1,11 @@
+pub(crate) mod compartment_mgr;
 pub(crate) mod composition_mgr;
 pub(crate) mod display_attribute;
 pub(crate) mod edit_session;
+pub(crate) mod input_settings;
 pub(crate) mod key_event_sink;
 pub(crate) mod language_bar;
+pub(crate) mod selection_window;
 pub(crate) mod text_edit_sink;
 pub(crate) mod text_service;
 pub(crate) mod thread_mgr_event_sink;
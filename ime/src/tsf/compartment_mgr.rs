@@ -0,0 +1,194 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use windows::core::{implement, AsImpl, Interface, Result, GUID, VARIANT};
+use windows::Win32::UI::TextServices::{
+    ITfCompartment, ITfCompartmentEventSink, ITfCompartmentEventSink_Impl, ITfCompartmentMgr,
+    ITfKeyEventSink, ITfLangBarItemButton, ITfSource, ITfThreadMgr,
+    GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION, GUID_COMPARTMENT_KEYBOARD_OPENCLOSE,
+};
+
+use engine::compartment::Compartments;
+
+use super::input_settings::InputSettings;
+use super::key_event_sink::KeyEventSink;
+use super::language_bar::LanguageBar;
+
+// https://learn.microsoft.com/ja-jp/windows/win32/tsf/predefined-compartments
+const COMPARTMENTS: [GUID; 2] = [
+    GUID_COMPARTMENT_KEYBOARD_OPENCLOSE,
+    GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION,
+];
+
+// キーボードのコンパートメント (IMEのオン/オフと変換モード) を入力モードと同期するクラス
+// タスクバーの表示やアプリからの切り替えはここを通る
+#[derive(Clone)]
+pub struct CompartmentMgr {
+    thread_mgr: ITfThreadMgr,
+    client_id: u32,
+    settings: InputSettings,
+    // 自分で書いている間の変更通知は無視する
+    writing: Rc<Cell<bool>>,
+    cookies: Rc<RefCell<Vec<(ITfSource, u32)>>>,
+    // 入力モードが外から変わったら、入力中の文字列にすぐ反映してもらう
+    key_event_sink: Rc<RefCell<Option<ITfKeyEventSink>>>,
+}
+
+impl CompartmentMgr {
+    pub fn new(thread_mgr: ITfThreadMgr, client_id: u32, settings: InputSettings) -> Self {
+        CompartmentMgr {
+            thread_mgr,
+            client_id,
+            settings,
+            writing: Rc::new(Cell::new(false)),
+            cookies: Rc::new(RefCell::new(Vec::new())),
+            key_event_sink: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    // KeyEventSinkもCompartmentMgrを持っているので、Deactivateのときに外す
+    pub fn set_key_event_sink(&self, sink: Option<ITfKeyEventSink>) {
+        self.key_event_sink.replace(sink);
+    }
+
+    // 言語バーやコンパートメントで入力モードが変わった
+    pub fn settings_changed(&self) -> Result<()> {
+        // 反映している間にセットし直されてもいいように、借りたままにしない
+        let sink = self.key_event_sink.borrow().clone();
+        match sink {
+            Some(sink) => {
+                let sink: &KeyEventSink = unsafe { sink.as_impl() };
+                sink.apply_settings()
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn compartment(&self, guid: &GUID) -> Result<ITfCompartment> {
+        let compartment_mgr: ITfCompartmentMgr = self.thread_mgr.cast()?;
+        unsafe { compartment_mgr.GetCompartment(guid) }
+    }
+
+    // まだ誰も書いていないコンパートメントは空なので0として読む
+    fn get(&self, guid: &GUID) -> Result<i32> {
+        let value = unsafe { self.compartment(guid)?.GetValue()? };
+        Ok(i32::try_from(&value).unwrap_or(0))
+    }
+
+    fn set(&self, guid: &GUID, value: i32) -> Result<()> {
+        unsafe {
+            self.compartment(guid)?
+                .SetValue(self.client_id, &VARIANT::from(value))
+        }
+    }
+
+    // 前のセッションやアプリが書いた値があれば入力モードにする、なければ今の入力モードを書く
+    // Activateのとき、言語バーができてから呼ぶ
+    pub fn adopt(&self) -> Result<()> {
+        let mut written = false;
+        for guid in COMPARTMENTS.iter() {
+            written |= !unsafe { self.compartment(guid)?.GetValue()? }.is_empty();
+        }
+        if !written {
+            return self.write();
+        }
+        self.apply(self.read()?);
+        Ok(())
+    }
+
+    // オンかオフかと変換モードは別に読む、閉じていてもオンにしたときのモードは残す
+    // 設定が変わったかどうかを返す
+    fn apply(&self, compartments: Compartments) -> bool {
+        let before = (self.settings.mode(), self.settings.on_mode());
+        if let Some(on_mode) = compartments.on_mode() {
+            self.settings.set_on_mode(on_mode);
+        }
+        self.settings.set_mode(compartments.mode());
+        before != (self.settings.mode(), self.settings.on_mode())
+    }
+
+    pub fn read(&self) -> Result<Compartments> {
+        Ok(Compartments {
+            open: self.get(&GUID_COMPARTMENT_KEYBOARD_OPENCLOSE)? != 0,
+            conversion: self.get(&GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION)? as u32,
+        })
+    }
+
+    // 今の入力モードをコンパートメントに書く、入力モードが変わるたびに呼ぶ
+    pub fn write(&self) -> Result<()> {
+        let compartments = Compartments::new(
+            self.settings.mode(),
+            self.settings.on_mode(),
+            self.settings.input_method(),
+        );
+        if self.read().ok() == Some(compartments) {
+            return Ok(());
+        }
+
+        self.writing.set(true);
+        let result = self
+            .set(
+                &GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION,
+                compartments.conversion as i32,
+            )
+            .and_then(|_| {
+                self.set(
+                    &GUID_COMPARTMENT_KEYBOARD_OPENCLOSE,
+                    compartments.open as i32,
+                )
+            });
+        self.writing.set(false);
+        result
+    }
+
+    pub fn advise(&self, sink: &ITfCompartmentEventSink) -> Result<()> {
+        for guid in COMPARTMENTS.iter() {
+            let source: ITfSource = self.compartment(guid)?.cast()?;
+            let cookie = unsafe { source.AdviseSink(&ITfCompartmentEventSink::IID, sink)? };
+            self.cookies.borrow_mut().push((source, cookie));
+        }
+        Ok(())
+    }
+
+    pub fn unadvise(&self) -> Result<()> {
+        for (source, cookie) in self.cookies.borrow_mut().drain(..) {
+            unsafe { source.UnadviseSink(cookie)? }
+        }
+        Ok(())
+    }
+}
+
+// コンパートメントが変わったときに呼ばれるクラス
+// 入力中の文字列は、KeyEventSinkがすぐに新しいモードに合わせる
+#[implement(ITfCompartmentEventSink)]
+pub struct CompartmentEventSink {
+    compartment_mgr: CompartmentMgr,
+    language_bar: ITfLangBarItemButton,
+}
+
+impl CompartmentEventSink {
+    pub fn new(compartment_mgr: CompartmentMgr, language_bar: ITfLangBarItemButton) -> Self {
+        CompartmentEventSink {
+            compartment_mgr,
+            language_bar,
+        }
+    }
+}
+
+impl ITfCompartmentEventSink_Impl for CompartmentEventSink_Impl {
+    fn OnChange(&self, rguid: *const GUID) -> Result<()> {
+        let guid = unsafe { *rguid };
+        if !COMPARTMENTS.contains(&guid) || self.compartment_mgr.writing.get() {
+            return Ok(());
+        }
+
+        let compartments = self.compartment_mgr.read()?;
+        if !self.compartment_mgr.apply(compartments) {
+            return Ok(());
+        }
+
+        let language_bar: &LanguageBar = unsafe { self.language_bar.as_impl() };
+        language_bar.update()?;
+        self.compartment_mgr.settings_changed()
+    }
+}
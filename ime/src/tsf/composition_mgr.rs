@@ -1,18 +1,24 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
 
 use windows::core::{IUnknown, Interface, Result, VARIANT};
 use windows::Win32::Foundation::{BOOL, RECT};
 use windows::Win32::UI::TextServices::{
     ITfCompartmentMgr, ITfComposition, ITfCompositionSink, ITfContext, ITfContextComposition,
     ITfDocumentMgr, ITfInsertAtSelection, ITfRange, GUID_COMPARTMENT_TRANSITORYEXTENSION_PARENT,
-    GUID_PROP_ATTRIBUTE, TF_ANCHOR_START, TF_DEFAULT_SELECTION, TF_HALTCOND, TF_HF_OBJECT,
-    TF_IAS_QUERYONLY, TF_SELECTION, TF_TF_MOVESTART,
+    GUID_PROP_ATTRIBUTE, TF_AE_NONE, TF_ANCHOR_START, TF_CONTEXT_EDIT_CONTEXT_FLAGS,
+    TF_DEFAULT_SELECTION, TF_ES_ASYNCDONTCARE, TF_ES_READWRITE, TF_ES_SYNC, TF_HALTCOND,
+    TF_HF_OBJECT, TF_IAS_QUERYONLY, TF_SELECTION, TF_SELECTIONSTYLE, TF_TF_MOVESTART,
 };
 
+use engine::preedit::{Attribute, Preedit};
+
 use std::mem::ManuallyDrop;
 
 use crate::ui::LocateEvent;
-use crate::utils::winutils::to_wide_16;
 
 use super::edit_session::EditSession;
 
@@ -22,22 +28,41 @@ pub struct CompositionMgr {
     context: Rc<RefCell<Option<ITfContext>>>,
     sink: ITfCompositionSink,
     client_id: u32,
-    display_attribute: u32,
+    // TextServiceで登録した表示属性のatom ("input" / "converted" / "focused")
+    display_attribute_atom: HashMap<&'static str, u32>,
     pub preedit: RefCell<String>,
+    // 編集セッションの要求の仕方、キー入力の外から編集するときだけ非同期を許す
+    session_flags: Rc<Cell<TF_CONTEXT_EDIT_CONTEXT_FLAGS>>,
 }
 
 impl CompositionMgr {
-    pub fn new(client_id: u32, sink: ITfCompositionSink, display_attribute: u32) -> Self {
+    pub fn new(
+        client_id: u32,
+        sink: ITfCompositionSink,
+        display_attribute_atom: HashMap<&'static str, u32>,
+    ) -> Self {
         CompositionMgr {
             composition: Rc::new(RefCell::new(None)),
             context: Rc::new(RefCell::new(None)),
             sink,
             client_id,
-            display_attribute,
+            display_attribute_atom,
             preedit: RefCell::new(String::new()),
+            session_flags: Rc::new(Cell::new(TF_ES_SYNC | TF_ES_READWRITE)),
         }
     }
 
+    // キー入力の処理の外 (ウィンドウメッセージなど) から編集する
+    // 編集は後で行われることがあるので、compositionがある前提で文字列を書き換えるだけにする
+    pub fn outside_key_event<T>(&self, edit: impl FnOnce() -> T) -> T {
+        let flags = self
+            .session_flags
+            .replace(TF_ES_ASYNCDONTCARE | TF_ES_READWRITE);
+        let result = edit();
+        self.session_flags.set(flags);
+        result
+    }
+
     pub fn start_composition(&self, context: ITfContext) -> Result<()> {
         let insert: ITfInsertAtSelection = context.cast()?;
         let context_composition: ITfContextComposition = context.cast()?;
@@ -47,6 +72,7 @@ impl CompositionMgr {
         EditSession::handle(
             self.client_id,
             context,
+            self.session_flags.get(),
             Rc::new({
                 let composition_clone = Rc::clone(&self.composition);
                 let sink = self.sink.clone();
@@ -68,6 +94,7 @@ impl CompositionMgr {
         EditSession::handle(
             self.client_id,
             self.context.borrow().clone().unwrap(),
+            self.session_flags.get(),
             Rc::new(move |cookie| unsafe {
                 composition.EndComposition(cookie)?;
                 Ok(())
@@ -78,35 +105,100 @@ impl CompositionMgr {
         Ok(())
     }
 
-    pub fn set_text(&self, text: &str) -> Result<()> {
-        self.preedit.replace(text.to_string());
+    fn atom(&self, attribute: Attribute) -> u32 {
+        let name = match attribute {
+            Attribute::Input => "input",
+            Attribute::Converted => "converted",
+            Attribute::Focused => "focused",
+        };
+        self.display_attribute_atom
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // 区切りごとに表示属性を付けて、キャレットを置く
+    // ITfRange::SetTextは長さを受け取るので、終端のNULは入れない
+    pub fn set_preedit(&self, preedit: &Preedit) -> Result<()> {
+        let text = preedit.text();
+        self.preedit.replace(text.clone());
         let composition = self.composition.borrow().clone().unwrap();
         let context = self.context.borrow().clone().unwrap();
-        let wide_text = to_wide_16(text);
-        let pvar = VARIANT::from(self.display_attribute as i32);
+        let wide_text: Vec<u16> = text.encode_utf16().collect();
+        let ranges: Vec<(i32, i32, VARIANT)> = preedit
+            .utf16_ranges()
+            .into_iter()
+            .map(|(range, attribute)| {
+                let atom = VARIANT::from(self.atom(attribute) as i32);
+                (range.start as i32, range.end as i32, atom)
+            })
+            .collect();
+        let caret = preedit.utf16_caret() as i32;
 
         EditSession::handle(
             self.client_id,
             self.context.borrow().clone().unwrap(),
+            self.session_flags.get(),
             Rc::new(move |cookie| unsafe {
                 let range = composition.GetRange()?;
                 range.SetText(cookie, 0, &wide_text)?;
 
                 let prop = context.GetProperty(&GUID_PROP_ATTRIBUTE)?;
-                prop.SetValue(cookie, &range, &pvar)?;
-                Ok(())
+                prop.Clear(cookie, &range)?;
+                for (start, end, atom) in &ranges {
+                    let segment = sub_range(cookie, &range, *start, *end)?;
+                    prop.SetValue(cookie, &segment, atom)?;
+                }
+
+                let selection = [TF_SELECTION {
+                    range: ManuallyDrop::new(Some(sub_range(cookie, &range, caret, caret)?)),
+                    style: TF_SELECTIONSTYLE {
+                        ase: TF_AE_NONE,
+                        fInterimChar: BOOL::from(false),
+                    },
+                }];
+                let result = context.SetSelection(cookie, &selection);
+                // ManuallyDropなので自分で解放する
+                let [selection] = selection;
+                drop(ManuallyDrop::into_inner(selection.range));
+                result
             }),
         )?;
 
         Ok(())
     }
 
+    // 文字列を確定する、下線などの表示属性は外してからcompositionを終わる
+    pub fn commit(&self, text: &str) -> Result<()> {
+        self.preedit.replace(String::new());
+        let composition = self.composition.borrow().clone().unwrap();
+        let context = self.context.borrow().clone().unwrap();
+        let wide_text: Vec<u16> = text.encode_utf16().collect();
+
+        EditSession::handle(
+            self.client_id,
+            self.context.borrow().clone().unwrap(),
+            self.session_flags.get(),
+            Rc::new(move |cookie| unsafe {
+                let range = composition.GetRange()?;
+                range.SetText(cookie, 0, &wide_text)?;
+
+                let prop = context.GetProperty(&GUID_PROP_ATTRIBUTE)?;
+                prop.Clear(cookie, &range)?;
+                Ok(())
+            }),
+        )?;
+
+        self.end_composition()
+    }
+
     pub fn get_pos(&self) -> Result<LocateEvent> {
         let rect = Rc::new(RefCell::new(RECT::default()));
 
         EditSession::handle(
             self.client_id,
             self.context.borrow().clone().unwrap(),
+            self.session_flags.get(),
             Rc::new({
                 let context = self.context.borrow().clone().unwrap();
                 let composition = self.composition.borrow().clone().unwrap();
@@ -157,6 +249,7 @@ impl CompositionMgr {
             EditSession::handle(
                 self.client_id,
                 parent_context.clone(),
+                self.session_flags.get(),
                 Rc::new({
                     // parent contextの取得
                     let preceding_text_clone = Rc::clone(&preceding_text);
@@ -217,3 +310,13 @@ impl CompositionMgr {
         }
     }
 }
+
+// compositionの先頭から数えて[start, end)の範囲 (UTF-16の単位)
+unsafe fn sub_range(cookie: u32, range: &ITfRange, start: i32, end: i32) -> Result<ITfRange> {
+    let sub_range = range.Clone()?;
+    sub_range.Collapse(cookie, TF_ANCHOR_START)?;
+    let mut shifted = 0;
+    sub_range.ShiftEnd(cookie, end, &mut shifted, std::ptr::null())?;
+    sub_range.ShiftStart(cookie, start, &mut shifted, std::ptr::null())?;
+    Ok(sub_range)
+}
@@ -1,6 +1,6 @@
 use windows::core::{implement, Result};
 use windows::Win32::UI::TextServices::{
-    ITfContext, ITfEditSession, ITfEditSession_Impl, TF_ES_READWRITE, TF_ES_SYNC,
+    ITfContext, ITfEditSession, ITfEditSession_Impl, TF_CONTEXT_EDIT_CONTEXT_FLAGS,
 };
 
 use std::rc::Rc;
@@ -16,21 +16,18 @@ impl EditSession {
         EditSession { callback }
     }
 
+    // キー入力の処理中ならTF_ES_SYNC、それ以外から呼ぶときは同期で取れないことがあるのでTF_ES_ASYNCDONTCARE
+    // 戻り値のHRESULTは編集処理の結果 (非同期になったときはTF_S_ASYNC)
     pub fn handle(
         client_id: u32,
         context: ITfContext,
+        flags: TF_CONTEXT_EDIT_CONTEXT_FLAGS,
         callback: Rc<dyn Fn(u32) -> Result<()>>,
     ) -> Result<()> {
         let session: ITfEditSession = EditSession::new(callback).into();
 
-        let result = unsafe {
-            context.RequestEditSession(client_id, &session, TF_ES_SYNC | TF_ES_READWRITE)
-        };
-
-        match result {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        }
+        let session_result = unsafe { context.RequestEditSession(client_id, &session, flags)? };
+        session_result.ok()
     }
 }
 
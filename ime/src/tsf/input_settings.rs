@@ -0,0 +1,52 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use engine::composition::InputMethod;
+use engine::mode::InputMode;
+
+// 言語バーとKeyEventSinkで共有する入力の設定
+// 同じスレッドからしか触らないのでRc<Cell>で足りる
+#[derive(Clone, Default)]
+pub struct InputSettings {
+    mode: Rc<Cell<InputMode>>,
+    // IMEをオンにしたときに戻るモード
+    on_mode: Rc<Cell<InputMode>>,
+    input_method: Rc<Cell<InputMethod>>,
+}
+
+impl InputSettings {
+    pub fn mode(&self) -> InputMode {
+        self.mode.get()
+    }
+
+    pub fn set_mode(&self, mode: InputMode) {
+        self.mode.set(mode);
+        if !mode.is_direct() {
+            self.on_mode.set(mode);
+        }
+    }
+
+    pub fn on_mode(&self) -> InputMode {
+        self.on_mode.get()
+    }
+
+    // 閉じたコンパートメントに残っていたモードなど、今のモードは変えずに覚えておく
+    pub fn set_on_mode(&self, on_mode: InputMode) {
+        if !on_mode.is_direct() {
+            self.on_mode.set(on_mode);
+        }
+    }
+
+    // エンジンも同じ場所を読み書きするので、どちらかだけが古くなることはない
+    pub fn shared_on_mode(&self) -> Rc<Cell<InputMode>> {
+        Rc::clone(&self.on_mode)
+    }
+
+    pub fn input_method(&self) -> InputMethod {
+        self.input_method.get()
+    }
+
+    pub fn set_input_method(&self, input_method: InputMethod) {
+        self.input_method.set(input_method);
+    }
+}
@@ -1,23 +1,42 @@
+use std::cell::RefCell;
 use std::sync::mpsc::Sender;
 
-use windows::core::{implement, Result};
+use windows::core::{implement, AsImpl, Result};
 use windows::Win32::{
     Foundation::{BOOL, LPARAM, WPARAM},
-    UI::TextServices::{ITfContext, ITfKeyEventSink, ITfKeyEventSink_Impl},
+    UI::Input::KeyboardAndMouse::{
+        GetKeyState, GetKeyboardType, VK_CAPITAL, VK_CONTROL, VK_KANA, VK_MENU, VK_NUMLOCK,
+        VK_SHIFT,
+    },
+    UI::TextServices::{ITfContext, ITfKeyEventSink, ITfKeyEventSink_Impl, ITfLangBarItemButton},
 };
 
+use engine::composition::{Action, CompositionEngine};
+use engine::layout::Layout;
+use engine::romaji::RomajiTable;
+use ipc::ipc_proto::{KeyEvent, Modifiers, Toggles};
 use ipc::socket::SocketManager;
 
 use crate::ui::{CandidateEvent, UiEvent};
 
+use super::compartment_mgr::CompartmentMgr;
 use super::composition_mgr::CompositionMgr;
+use super::input_settings::InputSettings;
+use super::language_bar::LanguageBar;
 
 // キーボードイベントを処理するクラス
+// 何をするかはCompositionEngineが決めて、ここではTSFとUIに反映するだけ
 #[implement(ITfKeyEventSink)]
 pub struct KeyEventSink {
     composition_mgr: CompositionMgr,
     socket_mgr: SocketManager,
     ui_proxy: Sender<UiEvent>,
+    engine: RefCell<CompositionEngine>,
+    // 入力モードが変わったら表示とコンパートメントを更新する
+    language_bar: ITfLangBarItemButton,
+    compartment_mgr: CompartmentMgr,
+    // 言語バーで切り替えられる
+    settings: InputSettings,
 }
 
 impl KeyEventSink {
@@ -25,19 +44,140 @@ impl KeyEventSink {
         composition_mgr: CompositionMgr,
         socket_mgr: SocketManager,
         ui_proxy: Sender<UiEvent>,
+        language_bar: ITfLangBarItemButton,
+        compartment_mgr: CompartmentMgr,
+        settings: InputSettings,
     ) -> Self {
+        let mut engine = CompositionEngine::new()
+            .with_layout(keyboard_layout())
+            .with_romaji_table(RomajiTable::configured())
+            .with_on_mode(settings.shared_on_mode());
+        engine.set_input_method(settings.input_method());
+        engine.set_mode(settings.mode());
         KeyEventSink {
             composition_mgr,
             socket_mgr,
             ui_proxy,
+            engine: RefCell::new(engine),
+            language_bar,
+            compartment_mgr,
+            settings,
         }
     }
+
+    // 言語バーやコンパートメントで変えられた設定を、すぐにエンジンと入力中の文字列に反映する
+    // キー入力の処理中ではないので、編集セッションは非同期になることがある
+    pub fn apply_settings(&self) -> Result<()> {
+        let actions = {
+            let mut engine = self.engine.borrow_mut();
+            engine.set_input_method(self.settings.input_method());
+            engine.set_mode(self.settings.mode())
+        };
+        self.composition_mgr
+            .outside_key_event(|| self.apply(None, actions))
+    }
+
+    // 候補ウィンドウで選ばれた候補を、注目している文節に反映する
+    // キー入力の処理中ではないので、編集セッションは非同期になることがある
+    pub fn select_candidate(&self, index: usize) -> Result<()> {
+        let actions = self.engine.borrow_mut().select_candidate(index);
+        self.composition_mgr
+            .outside_key_event(|| self.apply(None, actions))
+    }
+
+    // ウィンドウメッセージなど、呼び出し元に返せないエラーはサーバーのログに残す
+    pub fn report_error(&self, context: &str, error: windows::core::Error) {
+        let _ = self.socket_mgr.debug(format!("{}: {}", context, error));
+    }
+
+    // 入力中の文字列を確定させて、キーをそのままアプリに渡す
+    fn pass_through(&self, pic: Option<&ITfContext>) -> Result<BOOL> {
+        let actions = self.engine.borrow_mut().reset();
+        self.apply(pic, actions)?;
+
+        Ok(BOOL::from(false))
+    }
+
+    fn apply(&self, pic: Option<&ITfContext>, actions: Vec<Action>) -> Result<()> {
+        for action in actions {
+            match action {
+                Action::StartComposition => {
+                    self.composition_mgr
+                        .start_composition(pic.unwrap().clone())?;
+                }
+                Action::SetPreedit(preedit) => {
+                    self.composition_mgr.set_preedit(&preedit)?;
+                }
+                Action::CommitText(text) => {
+                    self.composition_mgr.commit(&text)?;
+                }
+                Action::EndComposition => {
+                    if self.composition_mgr.composition.borrow().is_some() {
+                        self.composition_mgr.end_composition()?;
+                    }
+                }
+                Action::ShowCandidates(candidates) => {
+                    self.ui_proxy
+                        .send(UiEvent::Candidate(CandidateEvent { candidates }))
+                        .unwrap();
+                    self.ui_proxy.send(UiEvent::Show).unwrap();
+                }
+                Action::HideCandidates => {
+                    self.ui_proxy.send(UiEvent::Hide).unwrap();
+                }
+                Action::MoveCandidates => {
+                    let pos = self.composition_mgr.get_pos()?;
+                    self.ui_proxy.send(UiEvent::Locate(pos)).unwrap();
+                }
+                Action::SetMode(mode) => {
+                    self.settings.set_mode(mode);
+                    self.compartment_mgr.write()?;
+                    let language_bar: &LanguageBar = unsafe { self.language_bar.as_impl() };
+                    language_bar.update()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_pressed(virtual_key: u16) -> bool {
+    // 最上位ビットが押されているかどうか
+    unsafe { GetKeyState(virtual_key as i32) < 0 }
 }
 
-#[derive(serde::Serialize)]
-pub struct KeyEvent {
-    pub r#type: String,
-    pub message: String,
+fn is_toggled(virtual_key: u16) -> bool {
+    // 最下位ビットがロックされているかどうか
+    unsafe { GetKeyState(virtual_key as i32) & 1 != 0 }
+}
+
+// 設定がなければ、接続されているキーボードの種類から決める
+// https://learn.microsoft.com/ja-jp/windows/win32/api/winuser/nf-winuser-getkeyboardtype
+fn keyboard_layout() -> Layout {
+    // 7は日本語キーボード
+    let detected = if unsafe { GetKeyboardType(0) } == 7 {
+        Layout::Jis
+    } else {
+        Layout::Us
+    };
+    Layout::configured(detected)
+}
+
+// https://learn.microsoft.com/ja-jp/windows/win32/inputdev/virtual-key-codes
+// wparamが仮想キーコード、lparamにスキャンコードやリピート回数が入っている
+fn key_event(wparam: WPARAM, lparam: LPARAM) -> KeyEvent {
+    KeyEvent::from_message(wparam.0, lparam.0)
+        .with_modifiers(Modifiers {
+            shift: is_pressed(VK_SHIFT.0),
+            ctrl: is_pressed(VK_CONTROL.0),
+            alt: is_pressed(VK_MENU.0),
+        })
+        .with_toggles(Toggles {
+            caps_lock: is_toggled(VK_CAPITAL.0),
+            kana_lock: is_toggled(VK_KANA.0),
+            num_lock: is_toggled(VK_NUMLOCK.0),
+        })
 }
 
 impl ITfKeyEventSink_Impl for KeyEventSink_Impl {
@@ -47,45 +187,29 @@ impl ITfKeyEventSink_Impl for KeyEventSink_Impl {
         _wparam: WPARAM,
         _lparam: LPARAM,
     ) -> Result<BOOL> {
-        // https://learn.microsoft.com/ja-jp/windows/win32/inputdev/virtual-key-codes
-        let code: u8 = _wparam.0.try_into().unwrap();
-
-        let message = serde_json::to_string(&KeyEvent {
-            r#type: "key".to_string(),
-            message: code.to_string(),
-        })
-        .unwrap();
-
-        let response = self.socket_mgr.get(message).unwrap();
-        let response: Vec<&str> = response.split(',').collect();
+        let event = key_event(_wparam, _lparam);
 
-        // let pos = self.composition_mgr.get_pos()?;
-
-        if self.composition_mgr.composition.borrow().clone().is_none() {
-            self.composition_mgr
-                .start_composition(pic.unwrap().clone())?;
+        // OnTestKeyDownを経由しないで呼ばれることもある
+        if !self.engine.borrow().wants_key(&event) {
+            return Ok(BOOL::from(false));
         }
 
-        self.composition_mgr
-            .set_text(&format!("{}", &response[0]))?;
-
-        let pos = self.composition_mgr.get_pos()?;
-
-        self.ui_proxy.send(UiEvent::Locate(pos)).unwrap();
-
-        self.ui_proxy
-            .send(UiEvent::Candidate(CandidateEvent {
-                candidates: response.iter().map(|s| s.to_string()).collect(),
-            }))
-            .unwrap();
+        let result = self.engine.borrow_mut().handle_key(&self.socket_mgr, event);
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            // 応答が遅いときは入力中の文字列をそのまま残して、キーだけアプリに渡す
+            Err(ipc::error::Error::Timeout) => return Ok(BOOL::from(false)),
+            // サーバーに繋がらない間は直接入力にする
+            Err(_) => return self.pass_through(pic),
+        };
 
-        self.ui_proxy.send(UiEvent::Show).unwrap();
+        self.apply(pic, outcome.actions)?;
 
-        Ok(BOOL::from(true))
+        Ok(BOOL::from(outcome.handled))
     }
 
     fn OnKeyUp(&self, _pic: Option<&ITfContext>, _wparam: WPARAM, _lparam: LPARAM) -> Result<BOOL> {
-        Ok(BOOL::from(true))
+        Ok(BOOL::from(false))
     }
 
     fn OnPreservedKey(
@@ -100,21 +224,24 @@ impl ITfKeyEventSink_Impl for KeyEventSink_Impl {
         Ok(())
     }
 
+    // ここでtrueを返したキーだけOnKeyDownに来る
     fn OnTestKeyDown(
         &self,
         _pic: Option<&ITfContext>,
         _wparam: WPARAM,
         _lparam: LPARAM,
     ) -> Result<BOOL> {
-        Ok(BOOL::from(true))
+        let event = key_event(_wparam, _lparam);
+        Ok(BOOL::from(self.engine.borrow().wants_key(&event)))
     }
 
+    // キーを離したときは何もしない
     fn OnTestKeyUp(
         &self,
         _pic: Option<&ITfContext>,
         _wparam: WPARAM,
         _lparam: LPARAM,
     ) -> Result<BOOL> {
-        Ok(BOOL::from(true))
+        Ok(BOOL::from(false))
     }
 }
@@ -1,7 +1,12 @@
+use std::cell::RefCell;
+
 use windows::core::{implement, IUnknown, Interface, Result, BSTR, GUID, PCWSTR};
 use windows::Win32::Foundation::E_INVALIDARG;
+use windows::Win32::Graphics::Gdi::HBITMAP;
 use windows::Win32::UI::TextServices::{
-    ITfLangBarItemSink, GUID_LBI_INPUTMODE, TF_LBI_STYLE_BTN_BUTTON, TF_LBI_STYLE_TEXTCOLORICON,
+    ITfLangBarItemSink, GUID_LBI_INPUTMODE, TF_LBI_ICON, TF_LBI_STATUS, TF_LBI_STATUS_BTN_TOGGLED,
+    TF_LBI_STYLE_BTN_MENU, TF_LBI_STYLE_TEXTCOLORICON, TF_LBI_TEXT, TF_LBI_TOOLTIP,
+    TF_LBMENUF_RADIOCHECKED, TF_LBMENUF_SEPARATOR,
 };
 use windows::Win32::{
     Foundation::{BOOL, POINT, RECT},
@@ -16,9 +21,24 @@ use windows::Win32::{
     },
 };
 
+use engine::composition::InputMethod;
+use engine::mode::InputMode;
+
 use crate::utils::globals::GUID_TEXT_SERVICE;
 use crate::{dll::DllModule, utils::globals::TEXTSERVICE_LANGBARITEMSINK_COOKIE};
 
+use super::compartment_mgr::CompartmentMgr;
+use super::input_settings::InputSettings;
+
+// res.hのアイコン
+const IDI_MODE_KANA: u16 = 102;
+const IDI_MODE_LATN: u16 = 103;
+
+// メニューの項目、0から入力モード、そのあとに入力方法
+const MENU_ROMAJI: u32 = 100;
+const MENU_KANA: u32 = 101;
+const MENU_SEPARATOR: u32 = u32::MAX;
+
 // https://github.com/MicrosoftDocs/win32/blob/docs/desktop-src/TSF/language-bar.md
 // https://github.com/microsoft/Windows-classic-samples/blob/main/Samples/Win7Samples/winui/input/tsf/textservice/textservice-step04/LanguageBar.cpp
 
@@ -26,21 +46,32 @@ use crate::{dll::DllModule, utils::globals::TEXTSERVICE_LANGBARITEMSINK_COOKIE};
 #[implement(ITfSource, ITfLangBarItem, ITfLangBarItemButton)]
 pub struct LanguageBar {
     thread_mgr: ITfThreadMgr,
+    // KeyEventSinkと共有する、メニューで入力モードと入力方法を切り替える
+    settings: InputSettings,
+    compartment_mgr: CompartmentMgr,
+    sink: RefCell<Option<ITfLangBarItemSink>>,
 }
 
 // これを用意しないと言語バーは表示されない
 static INFO: TF_LANGBARITEMINFO = TF_LANGBARITEMINFO {
     clsidService: GUID_TEXT_SERVICE,
     guidItem: GUID_LBI_INPUTMODE,
-    dwStyle: TF_LBI_STYLE_BTN_BUTTON | TF_LBI_STYLE_TEXTCOLORICON,
+    dwStyle: TF_LBI_STYLE_BTN_MENU | TF_LBI_STYLE_TEXTCOLORICON,
     ulSort: 0,
     szDescription: [0; 32],
 };
 
 impl LanguageBar {
-    pub fn new(thread_mgr: ITfThreadMgr) -> Result<ITfLangBarItemButton> {
+    pub fn new(
+        thread_mgr: ITfThreadMgr,
+        settings: InputSettings,
+        compartment_mgr: CompartmentMgr,
+    ) -> Result<ITfLangBarItemButton> {
         let this = LanguageBar {
             thread_mgr: thread_mgr.clone(),
+            settings,
+            compartment_mgr,
+            sink: RefCell::new(None),
         };
         let item: ITfLangBarItemButton = this.into();
         LanguageBar::add_item(thread_mgr.clone(), item.clone())?;
@@ -64,6 +95,22 @@ impl LanguageBar {
 
         Ok(())
     }
+
+    // 入力モードや入力方法が変わったら、表示を更新してもらう
+    pub fn update(&self) -> Result<()> {
+        if let Some(sink) = self.sink.borrow().as_ref() {
+            unsafe { sink.OnUpdate(TF_LBI_STATUS | TF_LBI_ICON | TF_LBI_TEXT | TF_LBI_TOOLTIP)? }
+        }
+        Ok(())
+    }
+
+    fn tooltip(&self) -> String {
+        let input_method = match self.settings.input_method() {
+            InputMethod::Romaji => "ローマ字入力",
+            InputMethod::Kana => "かな入力",
+        };
+        format!("{} ({})", self.settings.mode().description(), input_method)
+    }
 }
 
 impl ITfLangBarItem_Impl for LanguageBar_Impl {
@@ -74,8 +121,13 @@ impl ITfLangBarItem_Impl for LanguageBar_Impl {
         Ok(())
     }
 
+    // IMEがオンの間は押された状態で表示する
     fn GetStatus(&self) -> Result<u32> {
-        Ok(0)
+        if self.settings.mode().is_direct() {
+            Ok(0)
+        } else {
+            Ok(TF_LBI_STATUS_BTN_TOGGLED)
+        }
     }
 
     fn Show(&self, _f_show: BOOL) -> Result<()> {
@@ -83,28 +135,84 @@ impl ITfLangBarItem_Impl for LanguageBar_Impl {
     }
 
     fn GetTooltipString(&self) -> Result<BSTR> {
-        Ok(BSTR::from("GetTooltipString"))
+        Ok(BSTR::from(self.tooltip()))
     }
 }
 
 impl ITfLangBarItemButton_Impl for LanguageBar_Impl {
+    // クリックするとメニューが出る
     fn OnClick(&self, _click: TfLBIClick, _pt: &POINT, _prcarea: *const RECT) -> Result<()> {
         Ok(())
     }
 
-    fn InitMenu(&self, _pmenu: Option<&ITfMenu>) -> windows::core::Result<()> {
+    fn InitMenu(&self, pmenu: Option<&ITfMenu>) -> windows::core::Result<()> {
+        let Some(menu) = pmenu else {
+            return Ok(());
+        };
+
+        let mode = self.settings.mode();
+        let input_method = self.settings.input_method();
+        let mut items: Vec<(u32, &str, bool)> = InputMode::ALL
+            .iter()
+            .enumerate()
+            .map(|(id, item)| (id as u32, item.description(), *item == mode))
+            .collect();
+        items.push((MENU_SEPARATOR, "", false));
+        items.push((
+            MENU_ROMAJI,
+            "ローマ字入力",
+            input_method == InputMethod::Romaji,
+        ));
+        items.push((MENU_KANA, "かな入力", input_method == InputMethod::Kana));
+
+        for (id, text, checked) in items {
+            let flags = if id == MENU_SEPARATOR {
+                TF_LBMENUF_SEPARATOR
+            } else if checked {
+                TF_LBMENUF_RADIOCHECKED
+            } else {
+                0
+            };
+            let text: Vec<u16> = text.encode_utf16().collect();
+            unsafe {
+                menu.AddMenuItem(
+                    id,
+                    flags,
+                    HBITMAP::default(),
+                    HBITMAP::default(),
+                    &text,
+                    std::ptr::null_mut(),
+                )?;
+            }
+        }
         Ok(())
     }
 
-    fn OnMenuSelect(&self, _w_id: u32) -> windows::core::Result<()> {
-        Ok(())
+    // コンパートメントに書いてから、入力中の文字列もすぐに新しいモードにする
+    fn OnMenuSelect(&self, w_id: u32) -> windows::core::Result<()> {
+        match w_id {
+            MENU_ROMAJI => self.settings.set_input_method(InputMethod::Romaji),
+            MENU_KANA => self.settings.set_input_method(InputMethod::Kana),
+            id => match InputMode::ALL.get(id as usize) {
+                Some(&mode) => self.settings.set_mode(mode),
+                None => return Ok(()),
+            },
+        }
+        self.compartment_mgr.write()?;
+        self.update()?;
+        self.compartment_mgr.settings_changed()
     }
 
     fn GetIcon(&self) -> Result<HICON> {
+        let icon = if self.settings.mode().is_kana() {
+            IDI_MODE_KANA
+        } else {
+            IDI_MODE_LATN
+        };
         unsafe {
             let handle = LoadImageW(
                 DllModule::global().lock().unwrap().hinst,
-                PCWSTR(102 as *mut u16),
+                PCWSTR(icon as *mut u16),
                 IMAGE_ICON,
                 0,
                 0,
@@ -116,7 +224,7 @@ impl ITfLangBarItemButton_Impl for LanguageBar_Impl {
     }
 
     fn GetText(&self) -> Result<BSTR> {
-        Ok(BSTR::from("GetText"))
+        Ok(BSTR::from(self.settings.mode().label()))
     }
 }
 
@@ -128,10 +236,11 @@ impl ITfSource_Impl for LanguageBar_Impl {
             return Err(E_INVALIDARG.into());
         }
 
-        if punk.is_none() {
+        let Some(punk) = punk else {
             return Err(E_INVALIDARG.into());
-        }
+        };
 
+        self.sink.replace(Some(punk.cast()?));
         Ok(TEXTSERVICE_LANGBARITEMSINK_COOKIE)
     }
 
@@ -140,6 +249,7 @@ impl ITfSource_Impl for LanguageBar_Impl {
             return Err(CONNECT_E_CANNOTCONNECT.into());
         }
 
+        self.sink.replace(None);
         Ok(())
     }
 }
@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+use std::ffi::c_void;
+
+use windows::core::{w, AsImpl, Result};
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::UI::TextServices::ITfKeyEventSink;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, PostMessageW, RegisterClassW, HMENU,
+    HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP, WNDCLASSW,
+};
+
+use ipc::ipc_proto::CandidateSelection;
+
+use crate::dll::DllModule;
+
+use super::key_event_sink::KeyEventSink;
+
+// 候補ウィンドウで候補が選ばれた、wparamが候補のindex
+const WM_SELECT_CANDIDATE: u32 = WM_APP + 1;
+
+thread_local! {
+    // 受け取った選択は、このスレッドのKeyEventSinkに渡す
+    static KEY_EVENT_SINK: RefCell<Option<ITfKeyEventSink>> = const { RefCell::new(None) };
+}
+
+// 候補ウィンドウはUIスレッドで動くので、選ばれた候補はメッセージでTSFのスレッドに送る
+// そのためにTSFのスレッドで作る、メッセージ専用のウィンドウ
+pub struct SelectionWindow {
+    hwnd: HWND,
+}
+
+impl SelectionWindow {
+    pub fn create() -> Result<Self> {
+        let hinst = DllModule::global().lock().unwrap().hinst;
+        unsafe {
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(wndproc),
+                hInstance: hinst.into(),
+                lpszClassName: w!("AzookeySelectionWindow"),
+                ..Default::default()
+            };
+            // 2回目からは登録済みで失敗するが、そのまま使える
+            RegisterClassW(&class);
+
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE(0),
+                class.lpszClassName,
+                w!(""),
+                WINDOW_STYLE(0),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                HMENU::default(),
+                hinst,
+                None,
+            )?;
+            Ok(SelectionWindow { hwnd })
+        }
+    }
+
+    // 選ばれた候補を渡す先、Noneで外す
+    pub fn set_key_event_sink(&self, sink: Option<ITfKeyEventSink>) {
+        KEY_EVENT_SINK.with(|key_event_sink| key_event_sink.replace(sink));
+    }
+
+    // UIスレッドで呼ぶ関数、HWNDはスレッドをまたげないので値で持つ
+    pub fn sender(&self) -> impl Fn(CandidateSelection) + Send + 'static {
+        let hwnd = self.hwnd.0 as isize;
+        move |selection| unsafe {
+            let index = selection.selected_index.max(0) as usize;
+            let _ = PostMessageW(
+                HWND(hwnd as *mut c_void),
+                WM_SELECT_CANDIDATE,
+                WPARAM(index),
+                LPARAM(0),
+            );
+        }
+    }
+
+    pub fn destroy(&self) -> Result<()> {
+        self.set_key_event_sink(None);
+        unsafe { DestroyWindow(self.hwnd) }
+    }
+}
+
+unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if msg != WM_SELECT_CANDIDATE {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+
+    // 選択を反映している間にセットし直されてもいいように、借りたままにしない
+    let sink = KEY_EVENT_SINK.with(|key_event_sink| key_event_sink.borrow().clone());
+    if let Some(sink) = sink {
+        let sink: &KeyEventSink = sink.as_impl();
+        if let Err(error) = sink.select_candidate(wparam.0) {
+            sink.report_error("selecting a candidate failed", error);
+        }
+    }
+    LRESULT(0)
+}
@@ -6,11 +6,11 @@ use std::thread;
 use windows::core::{implement, AsImpl, Interface, Result};
 use windows::Win32::Foundation::{BOOL, E_FAIL};
 use windows::Win32::UI::TextServices::{
-    CLSID_TF_CategoryMgr, IEnumTfDisplayAttributeInfo, ITfCategoryMgr, ITfCompositionSink,
-    ITfCompositionSink_Impl, ITfDisplayAttributeInfo, ITfDisplayAttributeProvider,
-    ITfDisplayAttributeProvider_Impl, ITfKeyEventSink, ITfKeystrokeMgr, ITfLangBarItemButton,
-    ITfSource, ITfTextInputProcessor, ITfTextInputProcessor_Impl, ITfThreadMgr,
-    ITfThreadMgrEventSink,
+    CLSID_TF_CategoryMgr, IEnumTfDisplayAttributeInfo, ITfCategoryMgr, ITfCompartmentEventSink,
+    ITfCompositionSink, ITfCompositionSink_Impl, ITfDisplayAttributeInfo,
+    ITfDisplayAttributeProvider, ITfDisplayAttributeProvider_Impl, ITfKeyEventSink,
+    ITfKeystrokeMgr, ITfLangBarItemButton, ITfSource, ITfTextInputProcessor,
+    ITfTextInputProcessor_Impl, ITfThreadMgr, ITfThreadMgrEventSink,
 };
 
 use crate::ui::{CandidateList, UiEvent};
@@ -18,12 +18,17 @@ use crate::utils::globals::{
     GUID_DISPLAY_ATTRIBUTE_CONVERTED, GUID_DISPLAY_ATTRIBUTE_FOCUSED, GUID_DISPLAY_ATTRIBUTE_INPUT,
 };
 use crate::utils::winutils::co_create_inproc;
+use ipc::endpoint::Endpoint;
+use ipc::ipc_proto::notification;
 use ipc::socket::SocketManager;
 
+use super::compartment_mgr::{CompartmentEventSink, CompartmentMgr};
 use super::composition_mgr::CompositionMgr;
 use super::display_attribute;
+use super::input_settings::InputSettings;
 use super::key_event_sink::KeyEventSink;
 use super::language_bar::LanguageBar;
+use super::selection_window::SelectionWindow;
 use super::thread_mgr_event_sink::ThreadMgrEventSink;
 
 // すべてを取りまとめるメインのクラス
@@ -43,6 +48,12 @@ pub struct TextService {
     // language bar
     language_bar: RefCell<Option<ITfLangBarItemButton>>,
 
+    // 入力モードと、ローマ字入力 / かな入力、言語バーとKeyEventSinkで共有する
+    input_settings: InputSettings,
+
+    // compartment manager (IMEのオン/オフと変換モード)
+    compartment_mgr: RefCell<Option<CompartmentMgr>>,
+
     // key event sink
     key_event_sink: RefCell<Option<ITfKeyEventSink>>,
 
@@ -57,6 +68,9 @@ pub struct TextService {
 
     // ui proxy
     ui_proxy: RefCell<Option<Sender<UiEvent>>>,
+
+    // 候補ウィンドウで選ばれた候補を受け取る
+    selection_window: RefCell<Option<SelectionWindow>>,
 }
 
 impl TextService {
@@ -73,6 +87,10 @@ impl TextService {
 
             language_bar: RefCell::new(None),
 
+            input_settings: InputSettings::default(),
+
+            compartment_mgr: RefCell::new(None),
+
             key_event_sink: RefCell::new(None),
 
             display_attribute_atom: RefCell::new(HashMap::new()),
@@ -82,6 +100,8 @@ impl TextService {
             socket_mgr: RefCell::new(None),
 
             ui_proxy: RefCell::new(None),
+
+            selection_window: RefCell::new(None),
         }
     }
 
@@ -104,17 +124,36 @@ impl TextService {
             )?));
         self.client_id.replace(tid);
 
+        self.activate_compartment_mgr()?;
         self.activate_language_bar()?;
+        self.activate_compartment_event_sink()?;
         self.activate_display_attribute()?;
         self.activate_socket()?;
 
         let (tx, rx) = std::sync::mpsc::channel();
+        let selection_window = SelectionWindow::create()?;
+        let on_select = selection_window.sender();
+
+        thread::spawn(move || {
+            CandidateList::create(rx, on_select);
+        });
 
-        thread::spawn(|| {
-            CandidateList::create(rx);
+        // サーバーから届いた候補の選択も、候補ウィンドウと同じようにこのスレッドに送る
+        // SocketManagerがなくなれば受け取れなくなって終わる
+        let notifications = self.socket_mgr.borrow().as_ref().unwrap().subscribe();
+        let on_notify = selection_window.sender();
+        thread::spawn(move || {
+            for notification in notifications {
+                if let Some(notification::Payload::SelectCandidate(selection)) =
+                    notification.payload
+                {
+                    on_notify(selection);
+                }
+            }
         });
 
         self.ui_proxy.replace(Some(tx));
+        self.selection_window.replace(Some(selection_window));
 
         self.activate_composition_mgr()?;
         self.activate_thread_mgr_event_sink()?;
@@ -126,10 +165,12 @@ impl TextService {
     // deactivate()
     fn deactivate(&self) -> Result<()> {
         self.deactivate_thread_mgr_event_sink()?;
+        self.deactivate_compartment_mgr()?;
         self.deactivate_language_bar()?;
         self.deactivate_display_attribute()?;
         self.deactivate_composition_mgr()?;
         self.deactivate_key_event_sink()?;
+        self.deactivate_selection_window()?;
         self.deactivate_socket()?;
         Ok(())
     }
@@ -164,7 +205,12 @@ impl TextService {
 
     // language bar ("あ"とか"A"とかのやつ)
     fn activate_language_bar(&self) -> Result<()> {
-        let language_bar = LanguageBar::new(self.thread_mgr.borrow().clone().unwrap()).unwrap();
+        let language_bar = LanguageBar::new(
+            self.thread_mgr.borrow().clone().unwrap(),
+            self.input_settings.clone(),
+            self.compartment_mgr.borrow().clone().unwrap(),
+        )
+        .unwrap();
         self.language_bar.replace(Some(language_bar));
 
         Ok(())
@@ -180,6 +226,44 @@ impl TextService {
         Ok(())
     }
 
+    // Compartment (タスクバーやアプリがIMEをオン/オフしたり、変換モードを変えたりする)
+    fn activate_compartment_mgr(&self) -> Result<()> {
+        let compartment_mgr = CompartmentMgr::new(
+            self.thread_mgr.borrow().clone().unwrap(),
+            self.client_id.borrow().clone(),
+            self.input_settings.clone(),
+        );
+        self.compartment_mgr.replace(Some(compartment_mgr));
+
+        Ok(())
+    }
+
+    // 言語バーを更新するので、言語バーのあとで登録する
+    // 入力モードはコンパートメントに残っている値に合わせる
+    fn activate_compartment_event_sink(&self) -> Result<()> {
+        let compartment_mgr = self.compartment_mgr.borrow().clone().unwrap();
+        let sink: ITfCompartmentEventSink = CompartmentEventSink::new(
+            compartment_mgr.clone(),
+            self.language_bar.borrow().clone().unwrap(),
+        )
+        .into();
+        compartment_mgr.adopt()?;
+        compartment_mgr.advise(&sink)?;
+        let item = self.language_bar.borrow().clone().unwrap();
+        let language_bar: &LanguageBar = unsafe { item.as_impl() };
+        language_bar.update()?;
+
+        Ok(())
+    }
+
+    fn deactivate_compartment_mgr(&self) -> Result<()> {
+        if let Some(compartment_mgr) = self.compartment_mgr.borrow_mut().take() {
+            compartment_mgr.set_key_event_sink(None);
+            compartment_mgr.unadvise()?;
+        }
+        Ok(())
+    }
+
     // Display attribute (表示属性、下線入れたり色変えたり)
     fn activate_display_attribute(&self) -> Result<()> {
         let category_mgr = self.category_mgr.borrow().clone().unwrap();
@@ -212,9 +296,8 @@ impl TextService {
         let sink: ITfCompositionSink = this.cast()?;
 
         let display_attribute_atom = self.display_attribute_atom.borrow().clone();
-        let display_attribute = display_attribute_atom.get("focused").unwrap();
 
-        let composition_mgr = CompositionMgr::new(client_id, sink, *display_attribute);
+        let composition_mgr = CompositionMgr::new(client_id, sink, display_attribute_atom);
         self.composition_mgr.replace(Some(composition_mgr));
 
         Ok(())
@@ -232,6 +315,9 @@ impl TextService {
             self.composition_mgr.borrow().clone().unwrap(),
             self.socket_mgr.borrow().clone().unwrap(),
             self.ui_proxy.borrow().clone().unwrap(),
+            self.language_bar.borrow().clone().unwrap(),
+            self.compartment_mgr.borrow().clone().unwrap(),
+            self.input_settings.clone(),
         )
         .into();
 
@@ -241,7 +327,13 @@ impl TextService {
             source.AdviseKeyEventSink(self.client_id.borrow().clone(), &sink, BOOL::from(true))?;
         }
 
-        self.key_event_sink.borrow_mut().replace(sink.into());
+        if let Some(selection_window) = self.selection_window.borrow().as_ref() {
+            selection_window.set_key_event_sink(Some(sink.clone()));
+        }
+        if let Some(compartment_mgr) = self.compartment_mgr.borrow().as_ref() {
+            compartment_mgr.set_key_event_sink(Some(sink.clone()));
+        }
+        self.key_event_sink.borrow_mut().replace(sink);
 
         Ok(())
     }
@@ -255,13 +347,26 @@ impl TextService {
         Ok(())
     }
 
+    fn deactivate_selection_window(&self) -> Result<()> {
+        if let Some(selection_window) = self.selection_window.borrow_mut().take() {
+            selection_window.destroy()?;
+        }
+        Ok(())
+    }
+
     fn activate_socket(&self) -> Result<()> {
-        let socket_mgr = SocketManager::new()?;
+        // Activateでは待たない、最初の要求のときに繋ぐ
+        // サーバーが起動していなくても失敗させない、次のキー入力で再接続を試みる
+        let endpoint = Endpoint::discover();
+        let socket_mgr = SocketManager::lazy(endpoint.path());
         self.socket_mgr.replace(Some(socket_mgr));
         Ok(())
     }
 
     fn deactivate_socket(&self) -> Result<()> {
+        if let Some(socket_mgr) = self.socket_mgr.borrow_mut().take() {
+            let _ = socket_mgr.shutdown();
+        }
         Ok(())
     }
 }
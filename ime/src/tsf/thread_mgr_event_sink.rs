@@ -3,10 +3,11 @@ use windows::Win32::UI::TextServices::{
     ITfContext, ITfDocumentMgr, ITfThreadMgrEventSink, ITfThreadMgrEventSink_Impl,
 };
 
+use ipc::client::ConverterClient;
+use ipc::ipc_proto::Context;
 use ipc::socket::SocketManager;
 
 use super::composition_mgr::CompositionMgr;
-use super::key_event_sink::KeyEvent;
 
 // イベントを受け取るクラス、編集コンテキストを作成したり、破棄したりするときに呼ばれる
 #[implement(ITfThreadMgrEventSink)]
@@ -47,12 +48,10 @@ impl ITfThreadMgrEventSink_Impl for ThreadMgrEventSink_Impl {
         }
         let preceding_text = self.composition_mgr.get_preceding_text()?;
 
-        let message = serde_json::to_string(&KeyEvent {
-            r#type: "left".to_string(),
-            message: preceding_text,
-        })
-        .unwrap();
-        self.socket_mgr.post(message)?;
+        // 文脈が送れなくても入力には影響しないので無視する
+        let _ = self.socket_mgr.update_context(Context {
+            context: preceding_text,
+        });
 
         Ok(())
     }
@@ -7,8 +7,11 @@ use tao::{
     event_loop::{ControlFlow, EventLoopBuilder},
     window::WindowBuilder,
 };
+use windows::core::HSTRING;
 use windows::Win32::{
     Foundation::HWND,
+    System::Diagnostics::Debug::OutputDebugStringW,
+    System::Registry::HKEY_CURRENT_USER,
     UI::WindowsAndMessaging::{
         SetWindowLongW, ShowWindow, GWL_EXSTYLE, GWL_STYLE, SW_SHOWNOACTIVATE, WS_EX_NOACTIVATE,
         WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
@@ -16,6 +19,12 @@ use windows::Win32::{
 };
 use wry::WebViewBuilder;
 
+use engine::candidate;
+use engine::candidate_view::{CandidateView, RendererMessage, Theme, RENDERER_VERSION};
+use ipc::ipc_proto::CandidateSelection;
+
+use crate::utils::registry::RegKey;
+
 pub struct CandidateList;
 
 #[derive(Debug)]
@@ -24,9 +33,10 @@ pub struct LocateEvent {
     pub y: i32,
 }
 
+// ページ送りや選んでいる候補はエンジンが決めるので、ここでは今のページを表示するだけ
 #[derive(Debug)]
 pub struct CandidateEvent {
-    pub candidates: Vec<String>,
+    pub candidates: candidate::CandidateList,
 }
 
 pub enum UiEvent {
@@ -36,9 +46,20 @@ pub enum UiEvent {
     Hide,
 }
 
+// event loopで処理するもの
+enum UserEvent {
+    Candidates(candidate::CandidateList),
+    // webviewがページを読み込んだ、それまでに来た候補は取っておく
+    RendererReady,
+}
+
 impl CandidateList {
-    pub fn create(rx: Receiver<UiEvent>) -> Self {
-        let event_loop = EventLoopBuilder::<String>::with_user_event()
+    // 候補がクリックされたらon_selectを呼ぶ、呼ばれるのはこのスレッド
+    pub fn create(
+        rx: Receiver<UiEvent>,
+        on_select: impl Fn(CandidateSelection) + Send + 'static,
+    ) -> Self {
+        let event_loop = EventLoopBuilder::<UserEvent>::with_user_event()
             .with_any_thread(true)
             .build();
         let window = WindowBuilder::new()
@@ -72,33 +93,27 @@ impl CandidateList {
         // }
 
         let webview = WebViewBuilder::new(&window)
-            .with_html(
-                r#"
-            <html>
-                <body style="background: #D2D2D2">
-                    <ol>
-                        <li></li>
-                        <li></li>
-                        <li></li>
-                        <li></li>
-                        <li></li>
-                    </ol>
-                    <script>
-                        function update(str) {
-                            const newItems = str.split(',').map(item => item.trim());
-
-                            const listItems = document.querySelectorAll('li');
-
-                            listItems.forEach((item, index) => {
-                                if (index < newItems.length) {
-                                    item.textContent = newItems[index];
-                                }
-                            });
-                        }
-                    </script>
-                </body>
-            </html>"#,
-            )
+            .with_html(include_str!("candidate_window.html"))
+            .with_ipc_handler({
+                let event_loop_proxy = event_loop.create_proxy();
+                move |request| match RendererMessage::parse(request.body()) {
+                    Some(RendererMessage::Ready { version }) if version == RENDERER_VERSION => {
+                        let _ = event_loop_proxy.send_event(UserEvent::RendererReady);
+                    }
+                    Some(RendererMessage::Select { index }) => on_select(CandidateSelection {
+                        selected_index: index as i32,
+                    }),
+                    // 表示できなかった、サーバーにはつながっていないのでデバッガに出す
+                    Some(RendererMessage::Error { message }) => unsafe {
+                        OutputDebugStringW(&HSTRING::from(format!(
+                            "candidate window: {}\n",
+                            message
+                        )));
+                    },
+                    // 版が合わない
+                    _ => {}
+                }
+            })
             .build()
             .unwrap();
 
@@ -114,7 +129,7 @@ impl CandidateList {
                 )),
                 UiEvent::Candidate(event) => {
                     event_loop_proxy
-                        .send_event(event.candidates[0..5].join(","))
+                        .send_event(UserEvent::Candidates(event.candidates))
                         .unwrap();
                 }
                 UiEvent::Show => {
@@ -134,6 +149,12 @@ impl CandidateList {
             }
         });
 
+        let mut ready = false;
+        let mut pending: Option<String> = None;
+        // 候補のたびにレジストリを読まないように覚えておく、設定が変わったら読み直す
+        let mut current_theme = theme();
+        let mut shown: Option<candidate::CandidateList> = None;
+
         event_loop.run(move |event, _, control_flow| {
             *control_flow = ControlFlow::Wait;
 
@@ -143,11 +164,48 @@ impl CandidateList {
                     event: WindowEvent::CloseRequested,
                     ..
                 } => *control_flow = ControlFlow::Exit,
-                Event::UserEvent(data) => {
-                    let _ = webview.evaluate_script(&*format!("update('{}')", data));
+                // 候補はJSONにして値として渡す、webviewはそれを表示するだけ
+                Event::UserEvent(UserEvent::Candidates(candidates)) => {
+                    let script = CandidateView::new(&candidates, current_theme).render_script();
+                    if ready {
+                        let _ = webview.evaluate_script(&script);
+                    } else {
+                        pending = Some(script);
+                    }
+                    shown = Some(candidates);
+                }
+                // taoがWM_SETTINGCHANGEで知らせる、表示中のページも描き直す
+                Event::WindowEvent {
+                    event: WindowEvent::ThemeChanged(_),
+                    ..
+                } => {
+                    current_theme = theme();
+                    if let Some(candidates) = &shown {
+                        let script = CandidateView::new(candidates, current_theme).render_script();
+                        if ready {
+                            let _ = webview.evaluate_script(&script);
+                        } else {
+                            pending = Some(script);
+                        }
+                    }
+                }
+                Event::UserEvent(UserEvent::RendererReady) => {
+                    ready = true;
+                    if let Some(script) = pending.take() {
+                        let _ = webview.evaluate_script(&script);
+                    }
                 }
                 _ => (),
             }
         });
     }
 }
+
+// Windowsのアプリの色の設定 (ライト / ダーク) に合わせる
+fn theme() -> Theme {
+    let personalize = r"Software\Microsoft\Windows\CurrentVersion\Themes\Personalize";
+    match HKEY_CURRENT_USER.get_dword(personalize, "AppsUseLightTheme") {
+        Ok(0) => Theme::Dark,
+        _ => Theme::Light,
+    }
+}
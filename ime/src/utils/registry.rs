@@ -1,8 +1,10 @@
+use std::ffi::c_void;
+
 use windows::{
     core::{Result, HSTRING, PCWSTR},
     Win32::System::Registry::{
-        RegCloseKey, RegCreateKeyExW, RegDeleteTreeW, RegSetValueExW, HKEY, KEY_WRITE,
-        REG_OPTION_NON_VOLATILE, REG_SZ,
+        RegCloseKey, RegCreateKeyExW, RegDeleteTreeW, RegGetValueW, RegSetValueExW, HKEY,
+        KEY_WRITE, REG_OPTION_NON_VOLATILE, REG_SZ, RRF_RT_REG_DWORD,
     },
 };
 
@@ -12,6 +14,7 @@ use crate::utils::winutils::to_wide;
 pub trait RegKey {
     fn create_subkey(&self, subkey: &str) -> Result<HKEY>;
     fn set_string(&self, value_name: &str, value: &str) -> Result<()>;
+    fn get_dword(&self, subkey: &str, value_name: &str) -> Result<u32>;
     fn delete_tree(&self, subkey: &str) -> Result<()>;
     fn close(&self) -> Result<()>;
 }
@@ -54,6 +57,26 @@ impl RegKey for HKEY {
         }
     }
 
+    fn get_dword(&self, subkey: &str, value_name: &str) -> Result<u32> {
+        let subkey_w = HSTRING::from(subkey);
+        let value_name_w = HSTRING::from(value_name);
+        let mut value: u32 = 0;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        unsafe {
+            let result = RegGetValueW(
+                *self,
+                PCWSTR(subkey_w.as_ptr()),
+                PCWSTR(value_name_w.as_ptr()),
+                RRF_RT_REG_DWORD,
+                None,
+                Some(&mut value as *mut u32 as *mut c_void),
+                Some(&mut size),
+            );
+
+            check_win32!(result, value)
+        }
+    }
+
     fn delete_tree(&self, subkey: &str) -> Result<()> {
         let subkey_w = HSTRING::from(subkey);
         unsafe {
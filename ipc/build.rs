@@ -6,6 +6,7 @@ fn main() -> Result<()> {
             "proto/tsf.proto",
             "proto/window.proto",
             "proto/converter.proto",
+            "proto/envelope.proto",
         ],
         &["proto/"],
     )?;
@@ -0,0 +1,113 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::error::Result;
+use crate::ipc_proto::{
+    request, CandidatesRequest, CandidatesResponse, Context, ConversionRequest, ConversionResponse,
+    SelectCandidateRequest, ThreadId, UpdateWindowState,
+};
+
+// ConverterService / TSFService in converter.proto and tsf.proto
+pub trait ConverterClient {
+    fn convert(&self, request: ConversionRequest) -> Result<ConversionResponse>;
+    fn update_window(&self, state: UpdateWindowState) -> Result<()>;
+    fn select_candidate(&self, request: SelectCandidateRequest) -> Result<()>;
+    fn candidates(&self, request: CandidatesRequest) -> Result<CandidatesResponse>;
+    fn update_context(&self, context: Context) -> Result<()>;
+    fn update_thread_id(&self, thread_id: ThreadId) -> Result<()>;
+}
+
+// records every call and replays scripted conversion results, for tests
+// convert() and candidates() both take the next result from the same queue, in call order
+// once the queue is empty they answer with an empty response
+#[derive(Debug, Default)]
+pub struct MockClient {
+    responses: RefCell<VecDeque<Result<ConversionResponse>>>,
+    requests: RefCell<Vec<request::Payload>>,
+    // answers for select_candidate, Ok(()) once these run out
+    select_results: RefCell<VecDeque<Result<()>>>,
+}
+
+impl MockClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_response(&self, response: ConversionResponse) {
+        self.responses.borrow_mut().push_back(Ok(response));
+    }
+
+    pub fn push_error(&self, error: crate::error::Error) {
+        self.responses.borrow_mut().push_back(Err(error));
+    }
+
+    pub fn push_select_error(&self, error: crate::error::Error) {
+        self.select_results.borrow_mut().push_back(Err(error));
+    }
+
+    pub fn requests(&self) -> Vec<request::Payload> {
+        self.requests.borrow().clone()
+    }
+
+    pub fn clear_requests(&self) {
+        self.requests.borrow_mut().clear();
+    }
+}
+
+impl ConverterClient for MockClient {
+    fn convert(&self, request: ConversionRequest) -> Result<ConversionResponse> {
+        self.requests
+            .borrow_mut()
+            .push(request::Payload::Convert(request));
+        self.responses
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or_else(|| Ok(ConversionResponse::default()))
+    }
+
+    fn update_window(&self, state: UpdateWindowState) -> Result<()> {
+        self.requests
+            .borrow_mut()
+            .push(request::Payload::UpdateWindow(state));
+        Ok(())
+    }
+
+    fn select_candidate(&self, request: SelectCandidateRequest) -> Result<()> {
+        self.requests
+            .borrow_mut()
+            .push(request::Payload::SelectCandidate(request));
+        self.select_results
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(Ok(()))
+    }
+
+    // consumes the same queue as convert(), only the candidates of the result are used
+    fn candidates(&self, request: CandidatesRequest) -> Result<CandidatesResponse> {
+        self.requests
+            .borrow_mut()
+            .push(request::Payload::Candidates(request));
+        let response = self
+            .responses
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or_else(|| Ok(ConversionResponse::default()))?;
+        Ok(CandidatesResponse {
+            candidates: response.candidates,
+        })
+    }
+
+    fn update_context(&self, context: Context) -> Result<()> {
+        self.requests
+            .borrow_mut()
+            .push(request::Payload::UpdateContext(context));
+        Ok(())
+    }
+
+    fn update_thread_id(&self, thread_id: ThreadId) -> Result<()> {
+        self.requests
+            .borrow_mut()
+            .push(request::Payload::UpdateThreadId(thread_id));
+        Ok(())
+    }
+}
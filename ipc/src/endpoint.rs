@@ -0,0 +1,84 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+// overrides the socket path for both the IME and the server
+pub const SOCKET_ENV: &str = "AZOOKEY_SOCKET";
+
+// where the conversion server listens
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    path: PathBuf,
+}
+
+impl Endpoint {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Endpoint { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // $AZOOKEY_SOCKET if set, otherwise the per-user, per-session default
+    pub fn discover() -> Self {
+        Self::discover_with(None)
+    }
+
+    // an explicit override (e.g. from a config file) wins over the environment
+    pub fn discover_with(config_override: Option<&Path>) -> Self {
+        if let Some(path) = config_override {
+            return Endpoint::new(path);
+        }
+
+        match env::var_os(SOCKET_ENV) {
+            Some(path) if !path.is_empty() => Endpoint::new(path),
+            _ => Self::session_default(),
+        }
+    }
+
+    pub fn session_default() -> Self {
+        let name = socket_name(&current_user(), current_session(), cfg!(debug_assertions));
+        Endpoint::new(env::temp_dir().join(name))
+    }
+}
+
+// e.g. azookey-alice-1.sock, or azookey-alice-1-debug.sock for debug builds
+// other bytes of the user name are percent-encoded, so two users never share a socket
+pub fn socket_name(user: &str, session: u32, debug: bool) -> String {
+    let mut encoded = String::with_capacity(user.len());
+    for byte in user.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    let suffix = if debug { "-debug" } else { "" };
+
+    format!("azookey-{}-{}{}.sock", encoded, session, suffix)
+}
+
+fn current_user() -> String {
+    ["USERNAME", "USER"]
+        .iter()
+        .find_map(|key| env::var(key).ok().filter(|user| !user.is_empty()))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+#[cfg(windows)]
+fn current_session() -> u32 {
+    use windows::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+
+    let mut session = 0;
+    match unsafe { ProcessIdToSessionId(std::process::id(), &mut session) } {
+        Ok(()) => session,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(not(windows))]
+fn current_session() -> u32 {
+    env::var("XDG_SESSION_ID")
+        .ok()
+        .and_then(|session| session.parse().ok())
+        .unwrap_or(0)
+}
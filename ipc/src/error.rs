@@ -0,0 +1,112 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::string::FromUtf8Error;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // the peer closed the stream
+    Disconnected,
+    FrameTooLarge { len: usize, max: usize },
+    InvalidUtf8(FromUtf8Error),
+    Decode(prost::DecodeError),
+    // the server answered with an ErrorResponse
+    Server(String),
+    UnexpectedResponse { expected: &'static str },
+    // the conversion server could not be reached
+    Connect { path: PathBuf, source: io::Error },
+    // waiting for the reconnect backoff to expire
+    NotConnected,
+    IncompatibleServer { client: u32, server: u32 },
+    // no response before the deadline
+    Timeout,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Disconnected => write!(f, "connection closed by peer"),
+            Error::FrameTooLarge { len, max } => {
+                write!(
+                    f,
+                    "frame of {} bytes exceeds the limit of {} bytes",
+                    len, max
+                )
+            }
+            Error::InvalidUtf8(e) => write!(f, "frame is not valid utf-8: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode message: {}", e),
+            Error::Server(message) => write!(f, "server error: {}", message),
+            Error::UnexpectedResponse { expected } => {
+                write!(f, "unexpected response, expected {}", expected)
+            }
+            Error::Connect { path, source } => {
+                write!(f, "failed to connect to {}: {}", path.display(), source)
+            }
+            Error::NotConnected => write!(f, "not connected to the conversion server"),
+            Error::IncompatibleServer { client, server } => write!(
+                f,
+                "conversion server speaks protocol version {}, but this client requires version {}",
+                server, client
+            ),
+            Error::Timeout => write!(f, "request timed out"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::InvalidUtf8(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::Connect { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    // errors after which the stream can't be trusted anymore
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            Error::Io(_)
+                | Error::Disconnected
+                | Error::FrameTooLarge { .. }
+                | Error::Connect { .. }
+                | Error::NotConnected
+        )
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::InvalidUtf8(e)
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+#[cfg(windows)]
+impl From<Error> for windows::core::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e.into(),
+            e => windows::core::Error::new(windows::Win32::Foundation::E_FAIL, e.to_string()),
+        }
+    }
+}
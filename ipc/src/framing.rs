@@ -0,0 +1,115 @@
+use std::io::{ErrorKind, Read, Write};
+
+use crate::error::{Error, Result};
+
+// Every message on the wire is a 4 byte big-endian length followed by the payload.
+pub const HEADER_LEN: usize = 4;
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 4 * 1024 * 1024;
+
+const READ_CHUNK: usize = 4096;
+
+pub fn encode_frame(payload: &[u8], max_frame_size: usize) -> Result<Vec<u8>> {
+    if payload.len() > max_frame_size || payload.len() > u32::MAX as usize {
+        return Err(Error::FrameTooLarge {
+            len: payload.len(),
+            max: max_frame_size,
+        });
+    }
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+pub fn write_frame<W: Write + ?Sized>(writer: &mut W, payload: &[u8]) -> Result<()> {
+    let frame = encode_frame(payload, DEFAULT_MAX_FRAME_SIZE)?;
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_string<W: Write + ?Sized>(writer: &mut W, message: &str) -> Result<()> {
+    write_frame(writer, message.as_bytes())
+}
+
+// Reassembles frames from a byte stream.
+// Bytes that were already read are kept across calls, so a read that fails halfway
+// (e.g. a timeout) can simply be retried without losing the stream position.
+#[derive(Debug)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        FrameReader {
+            buffer: Vec::new(),
+            max_frame_size,
+        }
+    }
+
+    // returns a complete frame if one is already buffered
+    pub fn next_buffered(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.buffer.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(&self.buffer[..HEADER_LEN]);
+        let len = u32::from_be_bytes(header) as usize;
+
+        if len > self.max_frame_size {
+            return Err(Error::FrameTooLarge {
+                len,
+                max: self.max_frame_size,
+            });
+        }
+
+        if self.buffer.len() < HEADER_LEN + len {
+            return Ok(None);
+        }
+
+        let payload = self.buffer[HEADER_LEN..HEADER_LEN + len].to_vec();
+        self.buffer.drain(..HEADER_LEN + len);
+        Ok(Some(payload))
+    }
+
+    pub fn read_frame<R: Read + ?Sized>(&mut self, reader: &mut R) -> Result<Vec<u8>> {
+        let mut chunk = [0u8; READ_CHUNK];
+
+        loop {
+            if let Some(payload) = self.next_buffered()? {
+                return Ok(payload);
+            }
+
+            match reader.read(&mut chunk) {
+                Ok(0) => return Err(Error::Disconnected),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn read_string<R: Read + ?Sized>(&mut self, reader: &mut R) -> Result<String> {
+        let payload = self.read_frame(reader)?;
+        Ok(String::from_utf8(payload)?)
+    }
+
+    // discards any partially received frame
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
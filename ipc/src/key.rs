@@ -0,0 +1,132 @@
+use crate::ipc_proto::{Candidate, ConversionRequest, KeyEvent, Modifiers, Toggles};
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers {
+        shift: false,
+        ctrl: false,
+        alt: false,
+    };
+    pub const SHIFT: Modifiers = Modifiers {
+        shift: true,
+        ctrl: false,
+        alt: false,
+    };
+    pub const CTRL: Modifiers = Modifiers {
+        shift: false,
+        ctrl: true,
+        alt: false,
+    };
+    pub const ALT: Modifiers = Modifiers {
+        shift: false,
+        ctrl: false,
+        alt: true,
+    };
+}
+
+impl KeyEvent {
+    pub fn new(virtual_key: u32) -> Self {
+        KeyEvent {
+            virtual_key,
+            repeat_count: 1,
+            ..Default::default()
+        }
+    }
+
+    // https://learn.microsoft.com/ja-jp/windows/win32/inputdev/wm-keydown
+    // lparam: 0-15 repeat count, 16-23 scan code, 24 extended key, 30 previous key state
+    pub fn from_message(wparam: usize, lparam: isize) -> Self {
+        let lparam = lparam as u32;
+        KeyEvent {
+            // 仮想キーコードは1から254 (0xFE)、0xFFも含めて範囲外は0 (未定義) として扱う
+            virtual_key: u32::try_from(wparam)
+                .ok()
+                .filter(|virtual_key| (1..=0xFE).contains(virtual_key))
+                .unwrap_or(0),
+            scan_code: (lparam >> 16) & 0xFF,
+            extended: lparam & (1 << 24) != 0,
+            repeat_count: lparam & 0xFFFF,
+            repeat: lparam & (1 << 30) != 0,
+            modifiers: None,
+            toggles: None,
+            character: String::new(),
+        }
+    }
+
+    pub fn with_modifiers(mut self, modifiers: Modifiers) -> Self {
+        self.modifiers = Some(modifiers);
+        self
+    }
+
+    pub fn with_toggles(mut self, toggles: Toggles) -> Self {
+        self.toggles = Some(toggles);
+        self
+    }
+
+    pub fn with_character(mut self, character: char) -> Self {
+        self.character = character.to_string();
+        self
+    }
+
+    pub fn character(&self) -> Option<char> {
+        self.character.chars().next()
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers.unwrap_or_default()
+    }
+
+    pub fn toggles(&self) -> Toggles {
+        self.toggles.unwrap_or_default()
+    }
+
+    // Shift と CapsLock を合わせて、英字を大文字で入力するか
+    pub fn is_uppercase(&self) -> bool {
+        self.modifiers().shift != self.toggles().caps_lock
+    }
+}
+
+// 非推奨：キーごとにサーバーでローマ字を変換する古い送り方、IMEはfrom_readingを使う
+impl From<KeyEvent> for ConversionRequest {
+    fn from(key_event: KeyEvent) -> Self {
+        ConversionRequest {
+            virtual_key_code: key_event.virtual_key as i32,
+            key_event: Some(key_event),
+            ..Default::default()
+        }
+    }
+}
+
+impl Candidate {
+    // 読み全体を変換する候補
+    pub fn new(surface: impl Into<String>, reading: impl Into<String>) -> Self {
+        let reading = reading.into();
+        Candidate {
+            surface: surface.into(),
+            consumed_length: reading.chars().count() as u32,
+            reading,
+            annotation: String::new(),
+        }
+    }
+
+    pub fn with_annotation(mut self, annotation: impl Into<String>) -> Self {
+        self.annotation = annotation.into();
+        self
+    }
+}
+
+impl ConversionRequest {
+    // 読みはIMEが組み立てるので、サーバーは変換だけする
+    pub fn from_reading(reading: impl Into<String>) -> Self {
+        ConversionRequest {
+            reading: reading.into(),
+            ..Default::default()
+        }
+    }
+
+    // 先頭からclause_lengthsの長さで区切って、focused_clauseの文節の候補をもらう
+    pub fn with_clauses(mut self, clause_lengths: Vec<u32>, focused_clause: usize) -> Self {
+        self.clause_lengths = clause_lengths;
+        self.focused_clause = focused_clause as u32;
+        self
+    }
+}
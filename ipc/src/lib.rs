@@ -1,4 +1,11 @@
+pub mod client;
+pub mod endpoint;
+pub mod error;
+pub mod framing;
+pub mod key;
+pub mod protocol;
 pub mod socket;
+pub mod transport;
 pub mod ipc_proto {
     include!(concat!(env!("OUT_DIR"), "/ipc.rs"));
 }
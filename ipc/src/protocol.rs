@@ -0,0 +1,122 @@
+use std::io::{Read, Write};
+
+use prost::Message;
+
+use crate::error::{Error, Result};
+use crate::framing::{self, FrameReader};
+use crate::ipc_proto::{
+    request, response, CandidatesResponse, ConversionResponse, ErrorResponse, Hello, HelloResponse,
+    Notification, Request, Response,
+};
+
+// bump this whenever a change to the .proto files breaks older peers
+pub const PROTOCOL_VERSION: u32 = 5;
+
+// responses with this id are notifications pushed by the server
+pub const NOTIFICATION_ID: u64 = 0;
+
+impl Request {
+    pub fn new(payload: request::Payload) -> Self {
+        Request {
+            version: PROTOCOL_VERSION,
+            payload: Some(payload),
+            id: 0,
+        }
+    }
+
+    pub fn hello(client_name: impl Into<String>) -> Self {
+        Request::new(request::Payload::Hello(Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: client_name.into(),
+        }))
+    }
+
+    // debug messages are fire-and-forget, everything else gets a Response
+    pub fn expects_response(&self) -> bool {
+        !matches!(self.payload, Some(request::Payload::Debug(_)))
+    }
+}
+
+impl Response {
+    pub fn new(payload: response::Payload) -> Self {
+        Response {
+            version: PROTOCOL_VERSION,
+            payload: Some(payload),
+            id: NOTIFICATION_ID,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Response::new(response::Payload::Error(ErrorResponse {
+            message: message.into(),
+        }))
+    }
+
+    pub fn notification(notification: Notification) -> Self {
+        Response::new(response::Payload::Notification(notification))
+    }
+
+    // marks this response as the reply to the request with `id`
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn into_convert(self) -> Result<ConversionResponse> {
+        match self.payload {
+            Some(response::Payload::Convert(response)) => Ok(response),
+            other => Err(unexpected(other, "convert")),
+        }
+    }
+
+    pub fn into_candidates(self) -> Result<CandidatesResponse> {
+        match self.payload {
+            Some(response::Payload::Candidates(response)) => Ok(response),
+            other => Err(unexpected(other, "candidates")),
+        }
+    }
+
+    pub fn into_hello(self) -> Result<HelloResponse> {
+        match self.payload {
+            Some(response::Payload::Hello(response)) => Ok(response),
+            other => Err(unexpected(other, "hello")),
+        }
+    }
+
+    pub fn into_empty(self) -> Result<()> {
+        match self.payload {
+            Some(response::Payload::Empty(_)) => Ok(()),
+            other => Err(unexpected(other, "empty")),
+        }
+    }
+}
+
+// the client refuses servers that speak a different protocol version
+pub fn check_compatible(hello: &HelloResponse) -> Result<()> {
+    if hello.protocol_version != PROTOCOL_VERSION {
+        return Err(Error::IncompatibleServer {
+            client: PROTOCOL_VERSION,
+            server: hello.protocol_version,
+        });
+    }
+    Ok(())
+}
+
+fn unexpected(payload: Option<response::Payload>, expected: &'static str) -> Error {
+    match payload {
+        Some(response::Payload::Error(e)) => Error::Server(e.message),
+        _ => Error::UnexpectedResponse { expected },
+    }
+}
+
+pub fn write_message<W: Write + ?Sized, M: Message>(writer: &mut W, message: &M) -> Result<()> {
+    framing::write_frame(writer, &message.encode_to_vec())
+}
+
+pub fn read_message<R: Read + ?Sized, M: Message + Default>(
+    frame_reader: &mut FrameReader,
+    reader: &mut R,
+) -> Result<M> {
+    let payload = frame_reader.read_frame(reader)?;
+    Ok(M::decode(payload.as_slice())?)
+}
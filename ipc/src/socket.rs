@@ -1,122 +1,518 @@
-use std::{env::temp_dir, ffi::CString};
-
-use windows::core::Result;
-use windows::Win32::{
-    Foundation::E_FAIL,
-    Networking::WinSock::{
-        closesocket, connect, recv, send, socket, WSACleanup, WSAStartup, ADDRESS_FAMILY, AF_UNIX,
-        SEND_RECV_FLAGS, SOCKADDR_UN, SOCKET, SOCKET_ERROR, SOCK_STREAM,
-    },
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::client::ConverterClient;
+use crate::endpoint::Endpoint;
+use crate::error::{Error, Result};
+use crate::framing::FrameReader;
+use crate::ipc_proto::{
+    request, response, CandidatesRequest, CandidatesResponse, Context, ConversionRequest,
+    ConversionResponse, DebugMessage, Notification, Request, Response, SelectCandidateRequest,
+    ThreadId, UpdateWindowState,
 };
+use crate::protocol;
+use crate::transport::{self, Transport};
+
+// how hard to try when the conversion server is not reachable
+#[derive(Clone, Copy, Debug)]
+pub struct ReconnectPolicy {
+    // attempts made by SocketManager::connect before giving up
+    pub connect_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            connect_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+// deadlines for a single round trip
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    // Convert runs on the host application's UI thread for every key, so keep it short
+    pub keystroke: Duration,
+    pub default: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            keystroke: Duration::from_millis(200),
+            default: Duration::from_secs(2),
+        }
+    }
+}
+
+// a request that has been sent and whose response has not arrived yet
+// dropping it forgets the request, a late response is then discarded
+pub struct PendingRequest {
+    shared: Arc<Shared>,
+    id: u64,
+    receiver: Receiver<Result<Response>>,
+}
+
+impl PendingRequest {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn wait(self, timeout: Duration) -> Result<Response> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::Disconnected),
+        }
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        self.shared.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+struct Connection {
+    transport: Box<dyn Transport>,
+    // tells the reader thread of this connection apart from older ones
+    generation: u64,
+}
+
+impl Drop for Connection {
+    // the reader thread holds its own handle, shutting down wakes it up
+    fn drop(&mut self) {
+        let _ = self.transport.shutdown();
+    }
+}
+
+struct State {
+    connection: Option<Connection>,
+    generation: u64,
+    backoff: Duration,
+    // reconnecting is not attempted before this point
+    next_attempt: Option<Instant>,
+}
+
+struct Waiter {
+    generation: u64,
+    sender: Sender<Result<Response>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // requests waiting for a response, by request id
+    pending: Mutex<HashMap<u64, Waiter>>,
+    subscribers: Mutex<Vec<Sender<Notification>>>,
+    next_id: AtomicU64,
+}
+
+impl Shared {
+    // routes a message from the server to whoever is waiting for it
+    fn dispatch(&self, response: Response) {
+        if response.id == protocol::NOTIFICATION_ID {
+            if let Some(response::Payload::Notification(notification)) = response.payload {
+                self.subscribers
+                    .lock()
+                    .unwrap()
+                    .retain(|subscriber| subscriber.send(notification).is_ok());
+            }
+            return;
+        }
 
-#[derive(serde::Serialize)]
-pub struct KeyEvent {
-    pub r#type: String,
-    pub message: String,
+        // nobody is waiting anymore when the request timed out
+        if let Some(waiter) = self.pending.lock().unwrap().remove(&response.id) {
+            let _ = waiter.sender.send(Ok(response));
+        }
+    }
+
+    // called by the reader thread once its connection is gone
+    fn disconnected(&self, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .connection
+            .as_ref()
+            .is_some_and(|connection| connection.generation == generation)
+        {
+            state.connection = None;
+        }
+        drop(state);
+
+        self.pending.lock().unwrap().retain(|_, waiter| {
+            if waiter.generation != generation {
+                return true;
+            }
+            let _ = waiter.sender.send(Err(Error::Disconnected));
+            false
+        });
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct SocketManager {
-    socket: SOCKET,
+    // None when created from a bare transport, which can't be reopened
+    path: Option<PathBuf>,
+    policy: ReconnectPolicy,
+    timeouts: Timeouts,
+    shared: Arc<Shared>,
 }
 
 impl SocketManager {
+    // connects to the discovered endpoint, see Endpoint::discover
     pub fn new() -> Result<Self> {
-        unsafe {
-            let mut wsa_data = std::mem::zeroed();
-            WSAStartup(0x202, &mut wsa_data);
-
-            let temp_path = temp_dir();
-            let sock_path = temp_path.join("azookey.sock");
-
-            // Create socket
-            let sock = socket(AF_UNIX as i32, SOCK_STREAM, 0)?;
-            if sock == SOCKET::default() {
-                WSACleanup();
-                panic!("Failed to create socket");
-            }
+        Self::connect(Endpoint::discover().path())
+    }
 
-            // Prepare the sockaddr_un structure
-            let mut sock_addr: SOCKADDR_UN = std::mem::zeroed();
-            sock_addr.sun_family = ADDRESS_FAMILY(AF_UNIX);
-            let path = CString::new(sock_path.to_str().unwrap()).unwrap();
-            let path_bytes = path.as_bytes_with_nul();
-            let max_len = sock_addr.sun_path.len().min(path_bytes.len());
-            sock_addr.sun_path[..max_len].copy_from_slice(
-                &path_bytes[..max_len]
-                    .iter()
-                    .map(|&b| b as i8)
-                    .collect::<Vec<i8>>(),
-            );
-
-            // Connect to the server
-            let result = connect(
-                sock,
-                &sock_addr as *const SOCKADDR_UN as *const _,
-                std::mem::size_of::<SOCKADDR_UN>() as i32,
-            );
-            if result == SOCKET_ERROR {
-                closesocket(sock);
-                WSACleanup();
-                panic!("Failed to connect to the server");
-            }
+    // connects right away, retrying with backoff
+    pub fn connect(path: &Path) -> Result<Self> {
+        Self::connect_with_policy(path, ReconnectPolicy::default())
+    }
 
-            return Ok(Self { socket: sock });
+    pub fn connect_with_policy(path: &Path, policy: ReconnectPolicy) -> Result<Self> {
+        let mut backoff = policy.initial_backoff;
+        let mut attempt = 1;
+
+        loop {
+            match open(path, Instant::now() + Timeouts::default().default) {
+                Ok((transport, reader)) => {
+                    let manager = Self::lazy_with_policy(path, policy);
+                    let mut state = manager.shared.state.lock().unwrap();
+                    manager.attach(&mut state, transport, reader)?;
+                    drop(state);
+                    return Ok(manager);
+                }
+                Err(e) if attempt >= policy.connect_attempts || !e.is_connection_error() => {
+                    return Err(e)
+                }
+                Err(_) => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(policy.max_backoff);
+                    attempt += 1;
+                }
+            }
         }
     }
 
-    pub fn get(&self, message: String) -> Result<String> {
-        // send message
-        let bytes_sent = unsafe { send(self.socket, message.as_bytes(), SEND_RECV_FLAGS(0)) };
+    // doesn't connect until the first request
+    pub fn lazy(path: &Path) -> Self {
+        Self::lazy_with_policy(path, ReconnectPolicy::default())
+    }
 
-        if bytes_sent == SOCKET_ERROR {
-            return Err(E_FAIL.into());
+    pub fn lazy_with_policy(path: &Path, policy: ReconnectPolicy) -> Self {
+        Self::with_path(Some(path.to_path_buf()), policy)
+    }
+
+    // the handshake is expected to be done already
+    pub fn from_transport(transport: Box<dyn Transport>) -> Result<Self> {
+        let manager = Self::with_path(None, ReconnectPolicy::default());
+        let mut state = manager.shared.state.lock().unwrap();
+        manager.attach(&mut state, transport, FrameReader::new())?;
+        drop(state);
+        Ok(manager)
+    }
+
+    fn with_path(path: Option<PathBuf>, policy: ReconnectPolicy) -> Self {
+        SocketManager {
+            path,
+            policy,
+            timeouts: Timeouts::default(),
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    connection: None,
+                    generation: 0,
+                    backoff: policy.initial_backoff,
+                    next_attempt: None,
+                }),
+                pending: Mutex::new(HashMap::new()),
+                subscribers: Mutex::new(Vec::new()),
+                next_id: AtomicU64::new(1),
+            }),
         }
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.state.lock().unwrap().connection.is_some()
+    }
+
+    // notifications pushed by the server (e.g. WindowService::SelectCandidate) arrive here
+    pub fn subscribe(&self) -> Receiver<Notification> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn get(&self, request: Request) -> Result<Response> {
+        self.get_with_timeout(request, self.timeouts.default)
+    }
+
+    pub fn get_with_timeout(&self, request: Request, timeout: Duration) -> Result<Response> {
+        let deadline = Instant::now() + timeout;
+        let was_connected = self.is_connected();
+
+        // reconnecting and writing count against the same deadline as the response
+        let pending = match self.send_before(request.clone(), deadline) {
+            // the request never reached the server, try once more on a fresh connection
+            // a written request may already have been handled, so a lost response is never retried
+            Err(e) if was_connected && e.is_connection_error() => {
+                self.send_before(request, deadline)?
+            }
+            result => result?,
+        };
+        pending.wait(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    // sends a request and returns a handle to wait for its response
+    pub fn send(&self, request: Request) -> Result<PendingRequest> {
+        self.send_before(request, Instant::now() + self.timeouts.default)
+    }
+
+    // fails with Timeout when connecting or writing doesn't finish by `deadline`
+    fn send_before(&self, mut request: Request, deadline: Instant) -> Result<PendingRequest> {
+        request.id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel();
+        let pending = PendingRequest {
+            shared: self.shared.clone(),
+            id: request.id,
+            receiver,
+        };
+
+        let mut state = self.shared.state.lock().unwrap();
+        let connection = self.ensure_connected(&mut state, deadline)?;
+        // registered before writing, so the response can't arrive before its waiter
+        self.shared.pending.lock().unwrap().insert(
+            request.id,
+            Waiter {
+                generation: connection.generation,
+                sender,
+            },
+        );
+        let result = write_before(connection.transport.as_mut(), &request, deadline);
+        self.check(&mut state, result)?;
 
-        // Receive a response (uint32 max)
-        let mut buffer = [0u8; 4096];
-        let bytes_received = unsafe { recv(self.socket, &mut buffer, SEND_RECV_FLAGS(0)) };
-        if bytes_received > 0 {
-            let response = String::from_utf8_lossy(&buffer[..bytes_received as usize]);
+        Ok(pending)
+    }
+
+    pub fn post(&self, request: Request) -> Result<()> {
+        let deadline = Instant::now() + self.timeouts.default;
+        let mut state = self.shared.state.lock().unwrap();
+        let connection = self.ensure_connected(&mut state, deadline)?;
+        let result = write_before(connection.transport.as_mut(), &request, deadline);
+        self.check(&mut state, result)
+    }
+
+    pub fn debug(&self, message: String) -> Result<()> {
+        self.post(Request::new(request::Payload::Debug(DebugMessage {
+            message,
+        })))
+    }
 
-            return Ok(response.to_string());
-        } else {
-            return Err(E_FAIL.into());
+    pub fn shutdown(&self) -> Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(connection) = state.connection.take() {
+            connection.transport.shutdown()?;
         }
+        Ok(())
     }
 
-    pub fn post(&self, message: String) -> Result<()> {
-        // send message
-        let bytes_sent = unsafe { send(self.socket, message.as_bytes(), SEND_RECV_FLAGS(0)) };
+    fn ensure_connected<'a>(
+        &self,
+        state: &'a mut State,
+        deadline: Instant,
+    ) -> Result<&'a mut Connection> {
+        if state.connection.is_none() {
+            let path = self.path.as_ref().ok_or(Error::NotConnected)?;
 
-        if bytes_sent == SOCKET_ERROR {
-            return Err(E_FAIL.into());
+            if let Some(next_attempt) = state.next_attempt {
+                if Instant::now() < next_attempt {
+                    return Err(Error::NotConnected);
+                }
+            }
+
+            let opened = open(path, deadline);
+            match opened.and_then(|(transport, reader)| self.attach(state, transport, reader)) {
+                Ok(()) => {
+                    state.backoff = self.policy.initial_backoff;
+                    state.next_attempt = None;
+                }
+                Err(e) => {
+                    state.next_attempt = Some(Instant::now() + state.backoff);
+                    state.backoff = (state.backoff * 2).min(self.policy.max_backoff);
+                    return Err(e);
+                }
+            }
         }
 
+        Ok(state.connection.as_mut().unwrap())
+    }
+
+    // starts the reader thread for a freshly opened stream
+    fn attach(
+        &self,
+        state: &mut State,
+        transport: Box<dyn Transport>,
+        reader: FrameReader,
+    ) -> Result<()> {
+        // open() already cleared the handshake timeout, the reader thread never reads with one
+        let read_half = transport.try_clone()?;
+
+        state.generation += 1;
+        let generation = state.generation;
+        let shared = Arc::downgrade(&self.shared);
+        thread::Builder::new()
+            .name("ipc-reader".to_string())
+            .spawn(move || read_loop(shared, generation, read_half, reader))?;
+
+        state.connection = Some(Connection {
+            transport,
+            generation,
+        });
         Ok(())
     }
 
-    pub fn recv(&self) -> Result<String> {
-        // Receive a response (uint32 max)
-        let mut buffer = [0u8; 4096];
-        let bytes_received = unsafe { recv(self.socket, &mut buffer, SEND_RECV_FLAGS(0)) };
-        if bytes_received > 0 {
-            let response = String::from_utf8_lossy(&buffer[..bytes_received as usize]);
+    // drops the connection when the stream is broken, so the next call reconnects
+    // a write that timed out may have left half a frame on the stream, so that one goes too
+    fn check<T>(&self, state: &mut State, result: Result<T>) -> Result<T> {
+        if let Err(e) = &result {
+            if e.is_connection_error() || matches!(e, Error::Timeout) {
+                state.connection = None;
+            }
+        }
+        result
+    }
+}
+
+// owns the read half of a connection and hands every incoming message to Shared::dispatch
+// holds only a weak reference, so dropping the last SocketManager ends the thread
+fn read_loop(
+    shared: Weak<Shared>,
+    generation: u64,
+    mut transport: Box<dyn Transport>,
+    mut reader: FrameReader,
+) {
+    loop {
+        let response: Response = match protocol::read_message(&mut reader, &mut transport) {
+            Ok(response) => response,
+            // the frame itself was fine, only this message is lost
+            Err(Error::Decode(_)) => continue,
+            Err(_) => break,
+        };
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        shared.dispatch(response);
+    }
+
+    if let Some(shared) = shared.upgrade() {
+        shared.disconnected(generation);
+    }
+}
+
+const CLIENT_NAME: &str = concat!("ipc/", env!("CARGO_PKG_VERSION"));
+
+// connects and makes sure the server speaks our protocol version
+// the reader may already hold bytes that arrived after the handshake
+// a server that never answers fails with Timeout at `deadline` instead of hanging the caller,
+// the stream is dropped then, so it doesn't matter what state the timeout left it in
+fn open(path: &Path, deadline: Instant) -> Result<(Box<dyn Transport>, FrameReader)> {
+    let mut transport = transport::connect(path).map_err(|source| Error::Connect {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut reader = FrameReader::new();
+
+    write_before(transport.as_mut(), &Request::hello(CLIENT_NAME), deadline)?;
+    transport.set_read_timeout(Some(remaining(deadline)?))?;
+    let response: Response = timed(protocol::read_message(&mut reader, &mut transport))?;
+    protocol::check_compatible(&response.into_hello()?)?;
+    // the reader thread blocks until the next message, however long that takes
+    transport.set_read_timeout(None)?;
+
+    Ok((transport, reader))
+}
+
+// writes one message, giving up with Timeout at `deadline`
+fn write_before(transport: &mut dyn Transport, request: &Request, deadline: Instant) -> Result<()> {
+    transport.set_write_timeout(Some(remaining(deadline)?))?;
+    timed(protocol::write_message(transport, request))
+}
+
+// the time left until `deadline`, a zero timeout would mean blocking forever
+fn remaining(deadline: Instant) -> Result<Duration> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(Error::Timeout);
+    }
+    Ok(remaining)
+}
 
-            return Ok(response.to_string());
-        } else {
-            return Err(E_FAIL.into());
+// reads and writes that ran into the stream's timeout fail with WouldBlock or TimedOut
+fn timed<T>(result: Result<T>) -> Result<T> {
+    match result {
+        Err(Error::Io(e))
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Err(Error::Timeout)
         }
+        result => result,
     }
+}
 
-    pub fn debug(&self, message: String) -> Result<()> {
-        let message = serde_json::to_string(&KeyEvent {
-            r#type: "debug".to_string(),
-            message: message.to_string(),
-        })
-        .unwrap();
+impl ConverterClient for SocketManager {
+    fn convert(&self, request: ConversionRequest) -> Result<ConversionResponse> {
+        self.get_with_timeout(
+            Request::new(request::Payload::Convert(request)),
+            self.timeouts.keystroke,
+        )?
+        .into_convert()
+    }
 
-        self.post(message)?;
-        Ok(())
+    fn update_window(&self, state: UpdateWindowState) -> Result<()> {
+        self.get(Request::new(request::Payload::UpdateWindow(state)))?
+            .into_empty()
+    }
+
+    fn select_candidate(&self, request: SelectCandidateRequest) -> Result<()> {
+        self.get(Request::new(request::Payload::SelectCandidate(request)))?
+            .into_empty()
+    }
+
+    fn candidates(&self, request: CandidatesRequest) -> Result<CandidatesResponse> {
+        self.get_with_timeout(
+            Request::new(request::Payload::Candidates(request)),
+            self.timeouts.keystroke,
+        )?
+        .into_candidates()
+    }
+
+    fn update_context(&self, context: Context) -> Result<()> {
+        self.get(Request::new(request::Payload::UpdateContext(context)))?
+            .into_empty()
+    }
+
+    fn update_thread_id(&self, thread_id: ThreadId) -> Result<()> {
+        self.get(Request::new(request::Payload::UpdateThreadId(thread_id)))?
+            .into_empty()
     }
 }
@@ -0,0 +1,38 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod winsock;
+
+#[cfg(windows)]
+pub use winsock::WinSockStream;
+
+// a connected byte stream to the conversion server
+pub trait Transport: Read + Write + Send {
+    fn shutdown(&self) -> io::Result<()>;
+    // None blocks forever, reads that time out fail with WouldBlock or TimedOut
+    // only the handshake reads with a timeout, and a stream whose read timed out is dropped,
+    // so nothing relies on the stream still being usable after a timeout
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+    // bounds every write by the caller's deadline, so a server that stops reading can't hang it
+    // a write that timed out may have sent part of a frame, the stream is dropped then
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+    // another handle to the same stream, so one thread can read while another writes
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+}
+
+// connects to the AF_UNIX socket at `path` with the platform's native backend
+pub fn connect(path: &Path) -> io::Result<Box<dyn Transport>> {
+    #[cfg(unix)]
+    {
+        Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?))
+    }
+
+    #[cfg(windows)]
+    {
+        Ok(Box::new(WinSockStream::connect(path)?))
+    }
+}
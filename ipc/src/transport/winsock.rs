@@ -0,0 +1,160 @@
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use windows::Win32::Networking::WinSock::{
+    closesocket, connect, recv, send, setsockopt, shutdown, socket, WSACleanup, WSAGetLastError,
+    WSAStartup, ADDRESS_FAMILY, AF_UNIX, SD_BOTH, SEND_RECV_FLAGS, SOCKADDR_UN, SOCKET,
+    SOCKET_ERROR, SOCK_STREAM, SOL_SOCKET, SO_RCVTIMEO, SO_SNDTIMEO,
+};
+
+use super::Transport;
+
+fn last_error() -> io::Error {
+    io::Error::from_raw_os_error(unsafe { WSAGetLastError().0 })
+}
+
+// closes the socket once the last WinSockStream sharing it is dropped
+#[derive(Debug)]
+struct OwnedSocket(SOCKET);
+
+impl Drop for OwnedSocket {
+    fn drop(&mut self) {
+        unsafe {
+            closesocket(self.0);
+            WSACleanup();
+        }
+    }
+}
+
+// AF_UNIX stream socket on top of WinSock
+// WinSock allows recv and send on the same socket from different threads, so clones share it
+#[derive(Debug, Clone)]
+pub struct WinSockStream {
+    socket: Arc<OwnedSocket>,
+}
+
+impl WinSockStream {
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        let path = path
+            .to_str()
+            .and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid socket path"))?;
+        let path_bytes = path.as_bytes_with_nul();
+
+        unsafe {
+            // Prepare the sockaddr_un structure
+            let mut sock_addr: SOCKADDR_UN = std::mem::zeroed();
+            sock_addr.sun_family = ADDRESS_FAMILY(AF_UNIX);
+            if path_bytes.len() > sock_addr.sun_path.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "socket path is too long",
+                ));
+            }
+            sock_addr.sun_path[..path_bytes.len()]
+                .copy_from_slice(&path_bytes.iter().map(|&b| b as i8).collect::<Vec<i8>>());
+
+            let mut wsa_data = std::mem::zeroed();
+            let result = WSAStartup(0x202, &mut wsa_data);
+            if result != 0 {
+                return Err(io::Error::from_raw_os_error(result));
+            }
+
+            // Create socket
+            let sock = match socket(AF_UNIX as i32, SOCK_STREAM, 0) {
+                Ok(sock) => sock,
+                Err(e) => {
+                    WSACleanup();
+                    return Err(e.into());
+                }
+            };
+
+            // Connect to the server
+            let result = connect(
+                sock,
+                &sock_addr as *const SOCKADDR_UN as *const _,
+                std::mem::size_of::<SOCKADDR_UN>() as i32,
+            );
+            if result == SOCKET_ERROR {
+                let error = last_error();
+                closesocket(sock);
+                WSACleanup();
+                return Err(error);
+            }
+
+            Ok(WinSockStream {
+                socket: Arc::new(OwnedSocket(sock)),
+            })
+        }
+    }
+
+    // SO_RCVTIMEO / SO_SNDTIMEO take milliseconds as a DWORD, 0 means no timeout
+    fn set_timeout(&self, option: i32, timeout: Option<Duration>) -> io::Result<()> {
+        let millis = match timeout {
+            Some(timeout) => timeout.as_millis().clamp(1, u32::MAX as u128) as u32,
+            None => 0,
+        };
+        let result = unsafe {
+            setsockopt(
+                self.socket.0,
+                SOL_SOCKET,
+                option,
+                Some(&millis.to_ne_bytes()),
+            )
+        };
+        if result == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(())
+    }
+}
+
+impl Read for WinSockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_received = unsafe { recv(self.socket.0, buf, SEND_RECV_FLAGS(0)) };
+        if bytes_received == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(bytes_received as usize)
+    }
+}
+
+impl Write for WinSockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes_sent = unsafe { send(self.socket.0, buf, SEND_RECV_FLAGS(0)) };
+        if bytes_sent == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(bytes_sent as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for WinSockStream {
+    fn shutdown(&self) -> io::Result<()> {
+        if unsafe { shutdown(self.socket.0, SD_BOTH) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        // after a timed out recv the socket is in an indeterminate state, which is fine
+        // because the handshake drops the stream then and the reader thread never sets one
+        self.set_timeout(SO_RCVTIMEO, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_timeout(SO_SNDTIMEO, timeout)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+}
@@ -0,0 +1,83 @@
+use ipc::client::{ConverterClient, MockClient};
+use ipc::error::Error;
+use ipc::ipc_proto::{
+    request, Candidate, CandidatesRequest, ConversionRequest, ConversionResponse,
+    SelectCandidateRequest,
+};
+
+fn response(text: &str, candidates: &[&str]) -> ConversionResponse {
+    ConversionResponse {
+        converted_text: text.to_string(),
+        candidates: candidates
+            .iter()
+            .map(|surface| Candidate::new(*surface, text))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn scripted_results_are_replayed_in_order() {
+    let client = MockClient::new();
+    client.push_response(response("かん", &["缶"]));
+    client.push_error(Error::Timeout);
+    client.push_response(response("かんじ", &["漢字", "感じ"]));
+
+    let first = client.convert(ConversionRequest::from_reading("かん"));
+    assert_eq!(first.unwrap().converted_text, "かん");
+    assert!(matches!(
+        client.convert(ConversionRequest::from_reading("かん")),
+        Err(Error::Timeout)
+    ));
+    // candidates() takes its turn from the same queue
+    let candidates = client
+        .candidates(CandidatesRequest {
+            reading: "かんじ".to_string(),
+        })
+        .unwrap();
+    assert_eq!(candidates.candidates.len(), 2);
+
+    // an empty queue answers with an empty response
+    let rest = client.convert(ConversionRequest::from_reading("か"));
+    assert_eq!(rest.unwrap(), ConversionResponse::default());
+    let rest = client.candidates(CandidatesRequest::default());
+    assert!(rest.unwrap().candidates.is_empty());
+}
+
+#[test]
+fn every_call_is_recorded() {
+    let client = MockClient::new();
+    client
+        .convert(ConversionRequest::from_reading("かん"))
+        .unwrap();
+    let selection = SelectCandidateRequest {
+        selected_candidate_index: 1,
+        candidate: Some(Candidate::new("感", "かん")),
+    };
+    client.select_candidate(selection.clone()).unwrap();
+
+    assert_eq!(
+        client.requests(),
+        vec![
+            request::Payload::Convert(ConversionRequest::from_reading("かん")),
+            request::Payload::SelectCandidate(selection),
+        ]
+    );
+    client.clear_requests();
+    assert!(client.requests().is_empty());
+}
+
+#[test]
+fn selections_can_be_made_to_fail() {
+    let client = MockClient::new();
+    client.push_select_error(Error::Disconnected);
+
+    let selection = SelectCandidateRequest::default();
+    assert!(matches!(
+        client.select_candidate(selection.clone()),
+        Err(Error::Disconnected)
+    ));
+    // only the next selection fails, and it is still recorded
+    assert!(client.select_candidate(selection).is_ok());
+    assert_eq!(client.requests().len(), 2);
+}
@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use ipc::endpoint::{socket_name, Endpoint, SOCKET_ENV};
+
+#[test]
+fn socket_names_are_per_user_session_and_build() {
+    assert_eq!(socket_name("alice", 1, false), "azookey-alice-1.sock");
+    assert_eq!(socket_name("alice", 1, true), "azookey-alice-1-debug.sock");
+    assert_ne!(socket_name("alice", 1, false), socket_name("bob", 1, false));
+    assert_ne!(
+        socket_name("alice", 1, false),
+        socket_name("alice", 2, false)
+    );
+    assert_eq!(
+        socket_name("DOMAIN\\山田 太郎", 0, false),
+        "azookey-DOMAIN%5C%E5%B1%B1%E7%94%B0%20%E5%A4%AA%E9%83%8E-0.sock"
+    );
+    // names that differ only in characters outside [A-Za-z0-9_-] stay apart
+    assert_ne!(
+        socket_name("山田 太郎", 0, false),
+        socket_name("鈴木 一郎", 0, false)
+    );
+    assert_ne!(socket_name("a.b", 0, false), socket_name("a_b", 0, false));
+}
+
+// the only test in this binary that touches the environment
+#[test]
+fn discovery_order() {
+    std::env::remove_var(SOCKET_ENV);
+    let default = Endpoint::discover();
+    assert_eq!(default, Endpoint::session_default());
+    assert!(default.path().starts_with(std::env::temp_dir()));
+
+    std::env::set_var(SOCKET_ENV, "/tmp/from-env.sock");
+    assert_eq!(Endpoint::discover().path(), Path::new("/tmp/from-env.sock"));
+
+    let configured = Path::new("/tmp/from-config.sock");
+    assert_eq!(Endpoint::discover_with(Some(configured)).path(), configured);
+
+    std::env::remove_var(SOCKET_ENV);
+}
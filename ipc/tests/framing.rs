@@ -0,0 +1,148 @@
+use std::io::{self, Cursor, Read};
+
+use ipc::error::Error;
+use ipc::framing::{encode_frame, write_frame, write_string, FrameReader, HEADER_LEN};
+
+// hands out at most `chunk` bytes per read, and fails with WouldBlock every other call
+struct Trickle {
+    data: Vec<u8>,
+    pos: usize,
+    chunk: usize,
+    stall: bool,
+    stalled: bool,
+}
+
+impl Trickle {
+    fn new(data: Vec<u8>, chunk: usize, stall: bool) -> Self {
+        Trickle {
+            data,
+            pos: 0,
+            chunk,
+            stall,
+            stalled: false,
+        }
+    }
+}
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stall {
+            self.stalled = !self.stalled;
+            if self.stalled {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+        }
+        let end = (self.pos + self.chunk)
+            .min(self.data.len())
+            .min(self.pos + buf.len());
+        let n = end - self.pos;
+        buf[..n].copy_from_slice(&self.data[self.pos..end]);
+        self.pos = end;
+        Ok(n)
+    }
+}
+
+#[test]
+fn roundtrip_multiple_frames() {
+    let mut wire = Vec::new();
+    write_string(&mut wire, "こんにちは").unwrap();
+    write_string(&mut wire, "").unwrap();
+    write_string(&mut wire, "world").unwrap();
+
+    let mut cursor = Cursor::new(wire);
+    let mut reader = FrameReader::new();
+    assert_eq!(reader.read_string(&mut cursor).unwrap(), "こんにちは");
+    assert_eq!(reader.read_string(&mut cursor).unwrap(), "");
+    assert_eq!(reader.read_string(&mut cursor).unwrap(), "world");
+    assert!(matches!(
+        reader.read_string(&mut cursor),
+        Err(Error::Disconnected)
+    ));
+}
+
+#[test]
+fn frames_larger_than_a_single_read_survive() {
+    let message = "候補,".repeat(5000);
+    assert!(message.len() > 4096);
+
+    let mut wire = Vec::new();
+    write_string(&mut wire, &message).unwrap();
+
+    let mut cursor = Cursor::new(wire);
+    let mut reader = FrameReader::new();
+    assert_eq!(reader.read_string(&mut cursor).unwrap(), message);
+}
+
+#[test]
+fn multibyte_characters_split_across_reads() {
+    let message = "変換候補あいうえお";
+    let mut wire = Vec::new();
+    write_string(&mut wire, message).unwrap();
+
+    let mut stream = Trickle::new(wire, 1, false);
+    let mut reader = FrameReader::new();
+    assert_eq!(reader.read_string(&mut stream).unwrap(), message);
+}
+
+#[test]
+fn partial_frame_is_kept_across_failed_reads() {
+    let message = "漢字".repeat(100);
+    let mut wire = Vec::new();
+    write_string(&mut wire, &message).unwrap();
+
+    let mut stream = Trickle::new(wire, 7, true);
+    let mut reader = FrameReader::new();
+    let mut attempts = 0;
+    let received = loop {
+        attempts += 1;
+        match reader.read_string(&mut stream) {
+            Ok(received) => break received,
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    };
+    assert_eq!(received, message);
+    assert!(attempts > 1);
+}
+
+#[test]
+fn invalid_utf8_is_an_error() {
+    let mut wire = Vec::new();
+    write_frame(&mut wire, &[0xe3, 0x81]).unwrap();
+
+    let mut cursor = Cursor::new(wire);
+    let mut reader = FrameReader::new();
+    assert!(matches!(
+        reader.read_string(&mut cursor),
+        Err(Error::InvalidUtf8(_))
+    ));
+}
+
+#[test]
+fn oversized_frames_are_rejected() {
+    assert!(matches!(
+        encode_frame(&[0u8; 16], 8),
+        Err(Error::FrameTooLarge { len: 16, max: 8 })
+    ));
+
+    let wire = encode_frame(&[0u8; 16], 16).unwrap();
+    let mut cursor = Cursor::new(wire);
+    let mut reader = FrameReader::with_max_frame_size(8);
+    assert!(matches!(
+        reader.read_frame(&mut cursor),
+        Err(Error::FrameTooLarge { len: 16, max: 8 })
+    ));
+}
+
+#[test]
+fn truncated_frame_reports_disconnect() {
+    let mut wire = encode_frame(b"truncated", 1024).unwrap();
+    wire.truncate(HEADER_LEN + 3);
+
+    let mut cursor = Cursor::new(wire);
+    let mut reader = FrameReader::new();
+    assert!(matches!(
+        reader.read_frame(&mut cursor),
+        Err(Error::Disconnected)
+    ));
+}
@@ -0,0 +1,112 @@
+use std::io::Cursor;
+
+use ipc::error::Error;
+use ipc::framing::FrameReader;
+use ipc::ipc_proto::{
+    request, response, Candidate, ConversionRequest, ConversionResponse, KeyEvent, Request,
+    Response,
+};
+use ipc::protocol::{read_message, write_message, PROTOCOL_VERSION};
+
+#[test]
+fn request_roundtrip() {
+    let request = Request::new(request::Payload::Convert(ConversionRequest::from(
+        KeyEvent::new(0x41),
+    )));
+    assert_eq!(request.version, PROTOCOL_VERSION);
+
+    let mut wire = Vec::new();
+    write_message(&mut wire, &request).unwrap();
+
+    let decoded: Request = read_message(&mut FrameReader::new(), &mut Cursor::new(wire)).unwrap();
+    assert_eq!(decoded, request);
+}
+
+#[test]
+fn candidates_with_separators_survive() {
+    let texts = ["a,b", "'quoted'", "改行\nあり", ""];
+    let response = Response::new(response::Payload::Convert(ConversionResponse {
+        converted_text: "あ,い".to_string(),
+        candidates: texts
+            .iter()
+            .map(|text| Candidate::new(*text, *text))
+            .collect(),
+        ..Default::default()
+    }));
+
+    let mut wire = Vec::new();
+    write_message(&mut wire, &response).unwrap();
+
+    let decoded: Response = read_message(&mut FrameReader::new(), &mut Cursor::new(wire)).unwrap();
+    let decoded = decoded.into_convert().unwrap();
+    assert_eq!(decoded.converted_text, "あ,い");
+    assert_eq!(
+        decoded
+            .candidates
+            .iter()
+            .map(|candidate| candidate.surface.as_str())
+            .collect::<Vec<_>>(),
+        texts
+    );
+}
+
+#[test]
+fn error_responses_are_surfaced() {
+    let response = Response::error("dictionary not loaded");
+    assert!(matches!(
+        response.into_convert(),
+        Err(Error::Server(message)) if message == "dictionary not loaded"
+    ));
+
+    let response = Response::new(response::Payload::Empty(Default::default()));
+    assert!(matches!(
+        response.into_convert(),
+        Err(Error::UnexpectedResponse { .. })
+    ));
+}
+
+#[test]
+fn key_events_are_read_from_window_messages() {
+    // 'A' pressed for the third time while held, scan code 0x1E
+    let event = KeyEvent::from_message(0x41, 0x401E_0003);
+    assert_eq!(event.virtual_key, 0x41);
+    assert_eq!(event.scan_code, 0x1E);
+    assert_eq!(event.repeat_count, 3);
+    assert!(event.repeat);
+    assert!(!event.extended);
+
+    // right Ctrl is an extended key
+    let event = KeyEvent::from_message(0x11, 0x011D_0001);
+    assert!(event.extended);
+    assert!(!event.repeat);
+
+    // out of range virtual keys don't panic
+    assert_eq!(KeyEvent::from_message(0x1_0041, 0).virtual_key, 0);
+
+    // virtual keys end at 0xFE, 0xFF is not one
+    assert_eq!(KeyEvent::from_message(0xFE, 0).virtual_key, 0xFE);
+    assert_eq!(KeyEvent::from_message(0xFF, 0).virtual_key, 0);
+}
+
+#[test]
+fn shift_and_caps_lock_cancel_out() {
+    use ipc::ipc_proto::{Modifiers, Toggles};
+
+    let caps_lock = Toggles {
+        caps_lock: true,
+        ..Default::default()
+    };
+    assert!(!KeyEvent::new(0x41).is_uppercase());
+    assert!(KeyEvent::new(0x41)
+        .with_modifiers(Modifiers::SHIFT)
+        .is_uppercase());
+    assert!(KeyEvent::new(0x41).with_toggles(caps_lock).is_uppercase());
+    assert!(!KeyEvent::new(0x41)
+        .with_modifiers(Modifiers::SHIFT)
+        .with_toggles(caps_lock)
+        .is_uppercase());
+
+    let request = ConversionRequest::from(KeyEvent::new(0x41));
+    assert_eq!(request.virtual_key_code, 0x41);
+    assert_eq!(request.key_event.unwrap().virtual_key, 0x41);
+}
@@ -0,0 +1,440 @@
+#![cfg(unix)]
+
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ipc::client::ConverterClient;
+use ipc::error::Error;
+use ipc::framing::FrameReader;
+use ipc::ipc_proto::{
+    notification, request, response, Candidate, CandidateSelection, Context, ConversionRequest,
+    ConversionResponse, Empty, HelloResponse, KeyEvent, Notification, Request, Response,
+};
+use ipc::protocol::{read_message, write_message, PROTOCOL_VERSION};
+use ipc::socket::{ReconnectPolicy, SocketManager, Timeouts};
+
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ipc-{}-{}.sock", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// answers Convert with `candidate_count` candidates and everything else with Empty
+fn serve(stream: UnixStream, candidate_count: usize) {
+    serve_requests(stream, candidate_count, usize::MAX, PROTOCOL_VERSION);
+}
+
+fn serve_requests(stream: UnixStream, candidate_count: usize, limit: usize, protocol_version: u32) {
+    serve_slowly(
+        stream,
+        candidate_count,
+        limit,
+        protocol_version,
+        Duration::ZERO,
+    );
+}
+
+// like serve_requests, but takes `delay` to answer each Convert
+fn serve_slowly(
+    mut stream: UnixStream,
+    candidate_count: usize,
+    limit: usize,
+    protocol_version: u32,
+    delay: Duration,
+) {
+    let mut reader = FrameReader::new();
+    for _ in 0..limit {
+        let Ok(request) = read_message::<_, Request>(&mut reader, &mut stream) else {
+            break;
+        };
+        let id = request.id;
+        let payload = match request.payload {
+            Some(request::Payload::Hello(_)) => response::Payload::Hello(HelloResponse {
+                protocol_version,
+                server_name: "stand-in".to_string(),
+            }),
+            Some(request::Payload::Convert(request)) => {
+                thread::sleep(delay);
+                response::Payload::Convert(ConversionResponse {
+                    converted_text: request.virtual_key_code.to_string(),
+                    candidates: (0..candidate_count)
+                        .map(|i| Candidate::new(format!("候補{}", i), ""))
+                        .collect(),
+                    ..Default::default()
+                })
+            }
+            Some(request::Payload::Debug(_)) => continue,
+            _ => response::Payload::Empty(Empty {}),
+        };
+        if write_message(&mut stream, &Response::new(payload).with_id(id)).is_err() {
+            break;
+        }
+    }
+}
+
+fn spawn_server(name: &str, candidate_count: usize) -> PathBuf {
+    let path = socket_path(name);
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream, candidate_count);
+    });
+    path
+}
+
+#[test]
+fn client_round_trips_over_unix_socket() {
+    let path = spawn_server("roundtrip", 3);
+    let socket_mgr = SocketManager::connect(&path).unwrap();
+
+    socket_mgr.debug("hello".to_string()).unwrap();
+    socket_mgr
+        .update_context(Context {
+            context: "前の文".to_string(),
+        })
+        .unwrap();
+
+    let response = socket_mgr
+        .convert(ConversionRequest::from(KeyEvent::new(0x41)))
+        .unwrap();
+    assert_eq!(response.converted_text, "65");
+    assert_eq!(response.candidates.len(), 3);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn large_responses_are_not_truncated() {
+    let path = spawn_server("large", 2000);
+    let socket_mgr = SocketManager::connect(&path).unwrap();
+
+    let response = socket_mgr
+        .convert(ConversionRequest::from(KeyEvent::new(0x20)))
+        .unwrap();
+    assert_eq!(response.candidates.len(), 2000);
+    assert_eq!(response.candidates[1999].surface, "候補1999");
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn connecting_without_a_server_fails() {
+    let path = socket_path("missing");
+    assert!(SocketManager::connect(&path).is_err());
+}
+
+#[test]
+fn connecting_without_a_server_is_a_typed_error() {
+    let path = socket_path("typed");
+    let policy = ReconnectPolicy {
+        connect_attempts: 2,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    };
+    assert!(matches!(
+        SocketManager::connect_with_policy(&path, policy),
+        Err(Error::Connect { path: p, .. }) if p == path
+    ));
+}
+
+#[test]
+fn lazy_manager_connects_once_the_server_is_up() {
+    let path = socket_path("lazy");
+    let policy = ReconnectPolicy {
+        connect_attempts: 1,
+        initial_backoff: Duration::from_millis(20),
+        max_backoff: Duration::from_millis(20),
+    };
+    let socket_mgr = SocketManager::lazy_with_policy(&path, policy);
+    let request = ConversionRequest::from(KeyEvent::new(0x41));
+
+    assert!(matches!(
+        socket_mgr.convert(request.clone()),
+        Err(Error::Connect { .. })
+    ));
+    // still backing off
+    assert!(matches!(
+        socket_mgr.convert(request.clone()),
+        Err(Error::NotConnected)
+    ));
+
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream, 1);
+    });
+    thread::sleep(Duration::from_millis(30));
+
+    assert_eq!(socket_mgr.convert(request).unwrap().converted_text, "65");
+    assert!(socket_mgr.is_connected());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn reconnects_when_the_server_drops_the_connection() {
+    let path = socket_path("reconnect");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        // the first connection dies after the handshake and one request
+        let (stream, _) = listener.accept().unwrap();
+        serve_requests(stream, 1, 2, PROTOCOL_VERSION);
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream, 1);
+    });
+
+    let socket_mgr = SocketManager::connect(&path).unwrap();
+    for virtual_key_code in [0x41, 0x42, 0x43] {
+        let response = socket_mgr
+            .convert(KeyEvent::new(virtual_key_code).into())
+            .unwrap();
+        assert_eq!(response.converted_text, virtual_key_code.to_string());
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn requests_that_were_written_are_not_sent_again() {
+    let path = socket_path("no-resend");
+    let listener = UnixListener::bind(&path).unwrap();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        // every connection answers the handshake, reports the next request and hangs up
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = FrameReader::new();
+            let hello: Request = read_message(&mut reader, &mut stream).unwrap();
+            let response = Response::new(response::Payload::Hello(HelloResponse {
+                protocol_version: PROTOCOL_VERSION,
+                server_name: "stand-in".to_string(),
+            }))
+            .with_id(hello.id);
+            write_message(&mut stream, &response).unwrap();
+            if let Ok(request) = read_message::<_, Request>(&mut reader, &mut stream) {
+                let _ = sender.send(request.payload);
+            }
+        }
+    });
+
+    let socket_mgr = SocketManager::connect(&path).unwrap();
+    let result = socket_mgr.convert(KeyEvent::new(0x41).into());
+    assert!(matches!(result, Err(Error::Disconnected)));
+    // the server may have handled it, so it is reported instead of being retried
+    assert!(matches!(
+        receiver.recv_timeout(Duration::from_secs(1)),
+        Ok(Some(request::Payload::Convert(_)))
+    ));
+    assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn incompatible_servers_are_refused() {
+    let path = socket_path("incompatible");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve_requests(stream, 1, usize::MAX, PROTOCOL_VERSION + 1);
+    });
+
+    assert!(matches!(
+        SocketManager::connect(&path),
+        Err(Error::IncompatibleServer { client, server })
+            if client == PROTOCOL_VERSION && server == PROTOCOL_VERSION + 1
+    ));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn servers_that_never_answer_the_handshake_time_out() {
+    let path = socket_path("silent");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        // accepts every connection and keeps it open without saying anything
+        let streams: Vec<UnixStream> = listener
+            .incoming()
+            .map_while(|stream| stream.ok())
+            .collect();
+        drop(streams);
+    });
+
+    // connecting on the first keystroke only gets that keystroke's budget
+    let socket_mgr = SocketManager::lazy(&path).with_timeouts(Timeouts {
+        keystroke: Duration::from_millis(100),
+        default: Duration::from_secs(10),
+    });
+    let started = Instant::now();
+    assert!(matches!(
+        socket_mgr.convert(ConversionRequest::from(KeyEvent::new(0x41))),
+        Err(Error::Timeout)
+    ));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(!socket_mgr.is_connected());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn servers_that_stop_reading_time_out_the_write() {
+    let path = socket_path("stalled");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        // answers the handshake, then never reads again
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = FrameReader::new();
+        let hello: Request = read_message(&mut reader, &mut stream).unwrap();
+        let response = Response::new(response::Payload::Hello(HelloResponse {
+            protocol_version: PROTOCOL_VERSION,
+            server_name: "stand-in".to_string(),
+        }))
+        .with_id(hello.id);
+        write_message(&mut stream, &response).unwrap();
+        thread::sleep(Duration::from_secs(5));
+        drop(stream);
+    });
+
+    let socket_mgr = SocketManager::connect(&path)
+        .unwrap()
+        .with_timeouts(Timeouts {
+            keystroke: Duration::from_millis(100),
+            default: Duration::from_secs(10),
+        });
+    // far more than the socket buffers hold, so the write blocks
+    let reading = "あ".repeat(1024 * 1024);
+    let started = Instant::now();
+    assert!(matches!(
+        socket_mgr.convert(ConversionRequest::from_reading(reading)),
+        Err(Error::Timeout)
+    ));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    // half a frame may be on the stream, so it is not used again
+    assert!(!socket_mgr.is_connected());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn slow_conversions_time_out() {
+    let path = socket_path("timeout");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve_slowly(
+            stream,
+            1,
+            usize::MAX,
+            PROTOCOL_VERSION,
+            Duration::from_millis(500),
+        );
+    });
+
+    let socket_mgr = SocketManager::connect(&path)
+        .unwrap()
+        .with_timeouts(Timeouts {
+            keystroke: Duration::from_millis(50),
+            default: Duration::from_secs(2),
+        });
+    let started = Instant::now();
+    assert!(matches!(
+        socket_mgr.convert(ConversionRequest::from(KeyEvent::new(0x41))),
+        Err(Error::Timeout)
+    ));
+    assert!(started.elapsed() < Duration::from_millis(400));
+    // only the request is abandoned, the connection stays up
+    assert!(socket_mgr.is_connected());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn late_responses_are_not_mistaken_for_the_next_one() {
+    let path = socket_path("after-timeout");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve_slowly(
+            stream,
+            1,
+            usize::MAX,
+            PROTOCOL_VERSION,
+            Duration::from_millis(100),
+        );
+    });
+
+    let socket_mgr = SocketManager::connect(&path).unwrap();
+    assert!(matches!(
+        socket_mgr.get_with_timeout(
+            Request::new(request::Payload::Convert(ConversionRequest::from(
+                KeyEvent::new(0x41)
+            ))),
+            Duration::from_millis(30),
+        ),
+        Err(Error::Timeout)
+    ));
+
+    // the reply to 0x41 arrives first and has to be skipped
+    let response = socket_mgr
+        .get(Request::new(request::Payload::Convert(
+            ConversionRequest::from(KeyEvent::new(0x42)),
+        )))
+        .unwrap()
+        .into_convert()
+        .unwrap();
+    assert_eq!(response.converted_text, "66");
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn notifications_go_to_subscribers() {
+    let path = socket_path("notify");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = FrameReader::new();
+        let _: Request = read_message(&mut reader, &mut stream).unwrap();
+        let hello = Response::new(response::Payload::Hello(HelloResponse {
+            protocol_version: PROTOCOL_VERSION,
+            server_name: "stand-in".to_string(),
+        }));
+        write_message(&mut stream, &hello).unwrap();
+
+        // pushes a selection right before answering the next request
+        let request: Request = read_message(&mut reader, &mut stream).unwrap();
+        let notification = Response::notification(Notification {
+            payload: Some(notification::Payload::SelectCandidate(CandidateSelection {
+                selected_index: 2,
+            })),
+        });
+        write_message(&mut stream, &notification).unwrap();
+        let reply = Response::new(response::Payload::Convert(ConversionResponse {
+            converted_text: "あ".to_string(),
+            candidates: Vec::new(),
+            ..Default::default()
+        }));
+        write_message(&mut stream, &reply.with_id(request.id)).unwrap();
+    });
+
+    let socket_mgr = SocketManager::connect(&path).unwrap();
+    let notifications = socket_mgr.subscribe();
+
+    let response = socket_mgr
+        .convert(ConversionRequest::from(KeyEvent::new(0x41)))
+        .unwrap();
+    assert_eq!(response.converted_text, "あ");
+
+    let notification = notifications.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(
+        notification.payload,
+        Some(notification::Payload::SelectCandidate(CandidateSelection {
+            selected_index: 2
+        }))
+    );
+
+    let _ = std::fs::remove_file(&path);
+}
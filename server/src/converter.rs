@@ -0,0 +1,226 @@
+use std::sync::{Arc, Mutex};
+
+use ipc::ipc_proto::{Candidate, CandidatesResponse, Clause, ConversionResponse, KeyEvent};
+
+use crate::dictionary::Dictionary;
+use crate::romaji;
+
+// https://learn.microsoft.com/ja-jp/windows/win32/inputdev/virtual-key-codes
+const VK_BACK: i32 = 0x08;
+const VK_RETURN: i32 = 0x0D;
+const VK_ESCAPE: i32 = 0x1B;
+const VK_OEM_MINUS: i32 = 0xBD;
+
+// per-connection composition state
+pub struct Converter {
+    dictionary: Arc<Mutex<Dictionary>>,
+    input: String,
+    context: String,
+    // readings of the clauses from the last conversion
+    clauses: Vec<String>,
+    focused: usize,
+    // the candidates last sent to the client, SelectCandidate indexes into these
+    candidates: Vec<Candidate>,
+}
+
+impl Converter {
+    pub fn new(dictionary: Arc<Mutex<Dictionary>>) -> Self {
+        Converter {
+            dictionary,
+            input: String::new(),
+            context: String::new(),
+            clauses: Vec::new(),
+            focused: 0,
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn reading(&self) -> String {
+        romaji::to_hiragana(&self.input)
+    }
+
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+
+    pub fn set_context(&mut self, context: String) {
+        self.context = context;
+    }
+
+    // deprecated: the IME composes the reading itself and sends it with convert_clauses,
+    // this only serves older clients that send every key and let the server do romaji
+    pub fn handle_key(&mut self, key: &KeyEvent) -> ConversionResponse {
+        let modifiers = key.modifiers();
+        // shortcuts are not input
+        if modifiers.ctrl || modifiers.alt {
+            return self.convert();
+        }
+
+        match key.virtual_key as i32 {
+            VK_BACK => {
+                // drop the last kana, or the pending letters that have not become kana yet
+                let reading = self.reading();
+                let mut chars = reading.chars();
+                chars.next_back();
+                self.input = chars.as_str().to_string();
+            }
+            VK_RETURN | VK_ESCAPE => self.input.clear(),
+            // the client resolved the character from its keyboard layout
+            _ if !key.character.is_empty() => self.input.push_str(&key.character),
+            // older clients only send the virtual key
+            0x41..=0x5A => {
+                // uppercase letters are kept as they are, they never match the romaji table
+                let c = char::from_u32(key.virtual_key).unwrap_or_default();
+                if key.is_uppercase() {
+                    self.input.push(c);
+                } else {
+                    self.input.push(c.to_ascii_lowercase());
+                }
+            }
+            VK_OEM_MINUS => self.input.push('-'),
+            _ => {}
+        }
+
+        self.convert()
+    }
+
+    // the client composed the reading itself, replace ours with it
+    pub fn convert_reading(&mut self, reading: &str) -> ConversionResponse {
+        self.convert_clauses(reading, &[], 0)
+    }
+
+    // the client fixed the leading clause boundaries, the rest of the reading is split here
+    // the reading is already kana, letters in it are left as they are
+    pub fn convert_clauses(
+        &mut self,
+        reading: &str,
+        clause_lengths: &[u32],
+        focused: usize,
+    ) -> ConversionResponse {
+        self.input = reading.to_string();
+        self.respond(reading.to_string(), clause_lengths, focused)
+    }
+
+    pub fn convert(&mut self) -> ConversionResponse {
+        self.respond(self.reading(), &[], 0)
+    }
+
+    pub fn clauses(&self) -> &[String] {
+        &self.clauses
+    }
+
+    // candidates for one clause, focusing it
+    pub fn candidates(&mut self, reading: &str) -> CandidatesResponse {
+        if let Some(focused) = self.clauses.iter().position(|clause| clause == reading) {
+            self.focused = focused;
+        }
+        self.candidates = self.lookup(reading);
+        CandidatesResponse {
+            candidates: self.candidates.clone(),
+        }
+    }
+
+    // the selected candidate is remembered for next time
+    // clients report every clause of a commit, so the conversion itself is left alone
+    // a candidate for the start of a clause is learned under its own reading
+    // clients that send the candidate itself don't depend on which list was sent last
+    pub fn select(&mut self, index: usize, candidate: Option<Candidate>) {
+        if let Some(candidate) = candidate.as_ref().or(self.candidates.get(index)) {
+            self.dictionary
+                .lock()
+                .unwrap()
+                .learn(&candidate.reading, &candidate.surface);
+        }
+    }
+
+    fn respond(
+        &mut self,
+        reading: String,
+        clause_lengths: &[u32],
+        focused: usize,
+    ) -> ConversionResponse {
+        self.clauses = self.segment(&reading, clause_lengths);
+        self.focused = focused.min(self.clauses.len().saturating_sub(1));
+        self.candidates = self
+            .clauses
+            .get(self.focused)
+            .map(|clause| self.lookup(clause))
+            .unwrap_or_default();
+
+        ConversionResponse {
+            converted_text: reading,
+            candidates: self.candidates.clone(),
+            clauses: self
+                .clauses
+                .iter()
+                .map(|clause| Clause {
+                    reading: clause.clone(),
+                    surface: self
+                        .lookup(clause)
+                        .into_iter()
+                        .next()
+                        .map(|candidate| candidate.surface)
+                        .unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+
+    // lengths past the end of the reading are cut short, empty clauses are dropped
+    fn segment(&self, reading: &str, clause_lengths: &[u32]) -> Vec<String> {
+        let mut clauses = Vec::new();
+        let mut rest = reading;
+        for &length in clause_lengths {
+            let end = rest
+                .char_indices()
+                .nth(length as usize)
+                .map_or(rest.len(), |(index, _)| index);
+            if end > 0 {
+                clauses.push(rest[..end].to_string());
+            }
+            rest = &rest[end..];
+        }
+        clauses.extend(self.dictionary.lock().unwrap().segment(rest));
+        clauses
+    }
+
+    // words for the whole reading, then the reading as hiragana and katakana,
+    // then words for shorter and shorter prefixes of it
+    fn lookup(&self, reading: &str) -> Vec<Candidate> {
+        if reading.is_empty() {
+            return Vec::new();
+        }
+
+        let dictionary = self.dictionary.lock().unwrap();
+        let words = |reading: &str| -> Vec<Candidate> {
+            dictionary
+                .lookup(reading)
+                .iter()
+                .map(|surface| {
+                    let annotation = dictionary.annotation(reading, surface).unwrap_or_default();
+                    Candidate::new(surface.as_str(), reading).with_annotation(annotation)
+                })
+                .collect()
+        };
+
+        let mut candidates = words(reading);
+        for (surface, annotation) in [
+            (reading.to_string(), "ひらがな"),
+            (romaji::to_katakana(reading), "カタカナ"),
+        ] {
+            if !candidates
+                .iter()
+                .any(|candidate| candidate.surface == surface)
+            {
+                candidates.push(Candidate::new(surface, reading).with_annotation(annotation));
+            }
+        }
+
+        let chars: Vec<char> = reading.chars().collect();
+        for length in (1..chars.len()).rev() {
+            let prefix: String = chars[..length].iter().collect();
+            candidates.extend(words(&prefix));
+        }
+        candidates
+    }
+}
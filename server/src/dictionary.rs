@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// a few entries so the server is usable without a dictionary file
+const BUILTIN: &str = "\
+かんじ\t漢字
+かんじ\t感じ
+かんじ\t幹事
+へんかん\t変換
+にほんご\t日本語
+にほん\t日本
+にほん\t二本
+ご\t語
+ご\t後
+きょう\t今日
+きょう\t京
+あした\t明日
+わたし\t私
+こうほ\t候補
+にゅうりょく\t入力
+かぶしきがいしゃ\t株式会社
+かぶしきがいしゃ\t㈱\t環境依存
+";
+
+// reading -> surfaces, in the order they are suggested
+#[derive(Debug, Default, Clone)]
+pub struct Dictionary {
+    entries: HashMap<String, Vec<String>>,
+    // (reading, surface) -> a note shown next to the candidate
+    annotations: HashMap<(String, String), String>,
+}
+
+impl Dictionary {
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    // one `reading<TAB>surface` pair per line with an optional `<TAB>annotation`
+    // `#` starts a comment
+    pub fn parse(text: &str) -> Self {
+        let mut dictionary = Dictionary::default();
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            if let (Some(reading), Some(surface)) = (fields.next(), fields.next()) {
+                dictionary.insert(reading, surface);
+                if let Some(annotation) = fields.next().filter(|a| !a.is_empty()) {
+                    dictionary.annotate(reading, surface, annotation);
+                }
+            }
+        }
+        dictionary
+    }
+
+    pub fn insert(&mut self, reading: &str, surface: &str) {
+        let surfaces = self.entries.entry(reading.to_string()).or_default();
+        if !surfaces.iter().any(|s| s == surface) {
+            surfaces.push(surface.to_string());
+        }
+    }
+
+    pub fn annotate(&mut self, reading: &str, surface: &str, annotation: &str) {
+        self.annotations.insert(
+            (reading.to_string(), surface.to_string()),
+            annotation.to_string(),
+        );
+    }
+
+    pub fn annotation(&self, reading: &str, surface: &str) -> Option<&str> {
+        self.annotations
+            .get(&(reading.to_string(), surface.to_string()))
+            .map(String::as_str)
+    }
+
+    pub fn lookup(&self, reading: &str) -> &[String] {
+        self.entries
+            .get(reading)
+            .map(|surfaces| surfaces.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // splits a reading into clauses, taking the longest known word at each position
+    // runs of unknown kana stay together until a known word starts
+    pub fn segment(&self, reading: &str) -> Vec<String> {
+        let chars: Vec<char> = reading.chars().collect();
+        let mut clauses = Vec::new();
+        let mut unknown = String::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let word = (start + 1..=chars.len())
+                .rev()
+                .map(|end| chars[start..end].iter().collect::<String>())
+                .find(|word| self.entries.contains_key(word));
+            match word {
+                Some(word) => {
+                    if !unknown.is_empty() {
+                        clauses.push(std::mem::take(&mut unknown));
+                    }
+                    start += word.chars().count();
+                    clauses.push(word);
+                }
+                None => {
+                    unknown.push(chars[start]);
+                    start += 1;
+                }
+            }
+        }
+        if !unknown.is_empty() {
+            clauses.push(unknown);
+        }
+        clauses
+    }
+
+    // moves a selected surface to the front so it is suggested first next time
+    pub fn learn(&mut self, reading: &str, surface: &str) {
+        let surfaces = self.entries.entry(reading.to_string()).or_default();
+        surfaces.retain(|s| s != surface);
+        surfaces.insert(0, surface.to_string());
+    }
+}
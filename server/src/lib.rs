@@ -0,0 +1,59 @@
+pub mod converter;
+pub mod dictionary;
+pub mod romaji;
+pub mod service;
+
+#[cfg(unix)]
+pub use listener::{run, spawn};
+
+#[cfg(unix)]
+mod listener {
+    use std::io;
+    use std::os::unix::net::UnixListener;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, JoinHandle};
+
+    use crate::dictionary::Dictionary;
+    use crate::service;
+
+    fn bind(path: &Path) -> io::Result<UnixListener> {
+        // a stale socket file from a previous run would make bind fail
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        UnixListener::bind(path)
+    }
+
+    fn accept_loop(listener: UnixListener, dictionary: Arc<Mutex<Dictionary>>) {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("accept failed: {}", e);
+                    continue;
+                }
+            };
+            let dictionary = Arc::clone(&dictionary);
+            thread::spawn(move || {
+                if let Err(e) = service::serve(stream, dictionary) {
+                    eprintln!("connection closed: {}", e);
+                }
+            });
+        }
+    }
+
+    // serves every client on the socket at `path`, blocking forever
+    pub fn run(path: &Path, dictionary: Dictionary) -> io::Result<()> {
+        let listener = bind(path)?;
+        accept_loop(listener, Arc::new(Mutex::new(dictionary)));
+        Ok(())
+    }
+
+    // binds synchronously and serves on a background thread, for tests
+    pub fn spawn(path: &Path, dictionary: Dictionary) -> io::Result<JoinHandle<()>> {
+        let listener = bind(path)?;
+        let dictionary = Arc::new(Mutex::new(dictionary));
+        Ok(thread::spawn(move || accept_loop(listener, dictionary)))
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use ipc::endpoint::Endpoint;
+use server::dictionary::Dictionary;
+
+const USAGE: &str = "usage: azookey-server [--socket <path>] [--dictionary <path>]
+
+the socket defaults to $AZOOKEY_SOCKET, or a per-user, per-session path in the temp directory";
+
+fn main() -> ExitCode {
+    let mut socket = Endpoint::discover().path().to_path_buf();
+    let mut dictionary = Dictionary::builtin();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--socket", Some(path)) => socket = PathBuf::from(path),
+            ("--dictionary", Some(path)) => match Dictionary::load(path.as_ref()) {
+                Ok(loaded) => dictionary = loaded,
+                Err(e) => {
+                    eprintln!("failed to load {}: {}", path, e);
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    run(socket, dictionary)
+}
+
+#[cfg(unix)]
+fn run(socket: PathBuf, dictionary: Dictionary) -> ExitCode {
+    eprintln!("listening on {}", socket.display());
+    match server::run(&socket, dictionary) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn run(_socket: PathBuf, _dictionary: Dictionary) -> ExitCode {
+    eprintln!("the reference server only runs on Unix-domain sockets of unix platforms");
+    ExitCode::FAILURE
+}
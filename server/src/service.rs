@@ -0,0 +1,108 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use ipc::error::{Error, Result};
+use ipc::framing::FrameReader;
+use ipc::ipc_proto::{request, response, Empty, HelloResponse, KeyEvent, Request, Response};
+use ipc::protocol::{self, PROTOCOL_VERSION};
+
+use crate::converter::Converter;
+use crate::dictionary::Dictionary;
+
+const SERVER_NAME: &str = concat!("azookey-server/", env!("CARGO_PKG_VERSION"));
+
+// ConverterService + TSFService for a single client
+pub struct Session {
+    converter: Converter,
+}
+
+impl Session {
+    pub fn new(dictionary: Arc<Mutex<Dictionary>>) -> Self {
+        Session {
+            converter: Converter::new(dictionary),
+        }
+    }
+
+    pub fn converter(&self) -> &Converter {
+        &self.converter
+    }
+
+    // returns None for messages that don't expect a reply
+    pub fn handle(&mut self, request: Request) -> Option<Response> {
+        let id = request.id;
+        self.respond(request).map(|response| response.with_id(id))
+    }
+
+    fn respond(&mut self, request: Request) -> Option<Response> {
+        let payload = match request.payload {
+            // always answer the handshake, the client decides whether it can talk to us
+            Some(request::Payload::Hello(_)) => response::Payload::Hello(HelloResponse {
+                protocol_version: PROTOCOL_VERSION,
+                server_name: SERVER_NAME.to_string(),
+            }),
+            _ if request.version != PROTOCOL_VERSION => {
+                return Some(Response::error(format!(
+                    "unsupported protocol version {} (server speaks {})",
+                    request.version, PROTOCOL_VERSION
+                )));
+            }
+            Some(request::Payload::Convert(request)) if !request.reading.is_empty() => {
+                response::Payload::Convert(self.converter.convert_clauses(
+                    &request.reading,
+                    &request.clause_lengths,
+                    request.focused_clause as usize,
+                ))
+            }
+            Some(request::Payload::Convert(request)) => {
+                // deprecated: older clients send keys instead of the reading,
+                // and the oldest ones only the virtual key
+                let key = request
+                    .key_event
+                    .unwrap_or_else(|| KeyEvent::new(request.virtual_key_code as u32));
+                response::Payload::Convert(self.converter.handle_key(&key))
+            }
+            Some(request::Payload::Candidates(request)) => {
+                response::Payload::Candidates(self.converter.candidates(&request.reading))
+            }
+            Some(request::Payload::SelectCandidate(request)) => {
+                self.converter.select(
+                    request.selected_candidate_index.max(0) as usize,
+                    request.candidate,
+                );
+                response::Payload::Empty(Empty {})
+            }
+            Some(request::Payload::UpdateContext(context)) => {
+                self.converter.set_context(context.context);
+                response::Payload::Empty(Empty {})
+            }
+            Some(request::Payload::UpdateWindow(_)) | Some(request::Payload::UpdateThreadId(_)) => {
+                response::Payload::Empty(Empty {})
+            }
+            Some(request::Payload::Debug(message)) => {
+                eprintln!("[debug] {}", message.message);
+                return None;
+            }
+            None => return Some(Response::error("empty request")),
+        };
+
+        Some(Response::new(payload))
+    }
+}
+
+// serves one connection until the client goes away
+pub fn serve<S: Read + Write>(mut stream: S, dictionary: Arc<Mutex<Dictionary>>) -> Result<()> {
+    let mut session = Session::new(dictionary);
+    let mut reader = FrameReader::new();
+
+    loop {
+        let request: Request = match protocol::read_message(&mut reader, &mut stream) {
+            Ok(request) => request,
+            Err(Error::Disconnected) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if let Some(response) = session.handle(request) {
+            protocol::write_message(&mut stream, &response)?;
+        }
+    }
+}
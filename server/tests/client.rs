@@ -0,0 +1,346 @@
+#![cfg(unix)]
+
+use std::path::PathBuf;
+
+use ipc::client::ConverterClient;
+use ipc::ipc_proto::{
+    Candidate, CandidatesRequest, Context, ConversionRequest, ConversionResponse, KeyEvent,
+    Modifiers, SelectCandidateRequest,
+};
+use ipc::socket::SocketManager;
+use server::dictionary::Dictionary;
+
+fn start(name: &str) -> (SocketManager, PathBuf) {
+    let path = std::env::temp_dir().join(format!("server-{}-{}.sock", name, std::process::id()));
+    server::spawn(&path, Dictionary::builtin()).unwrap();
+    (SocketManager::connect(&path).unwrap(), path)
+}
+
+// sends keys the deprecated way, so the server's romaji path stays covered
+// uppercase letters are typed with Shift
+fn type_keys(client: &impl ConverterClient, keys: &str) -> ConversionResponse {
+    let mut response = ConversionResponse::default();
+    for key in keys.chars() {
+        let virtual_key = match key {
+            '\u{8}' => 0x08,
+            '-' => 0xBD,
+            c => c.to_ascii_uppercase() as u32,
+        };
+        let modifiers = if key.is_ascii_uppercase() {
+            Modifiers::SHIFT
+        } else {
+            Modifiers::NONE
+        };
+        response = client
+            .convert(KeyEvent::new(virtual_key).with_modifiers(modifiers).into())
+            .unwrap();
+    }
+    response
+}
+
+fn texts(response: &ConversionResponse) -> Vec<&str> {
+    response
+        .candidates
+        .iter()
+        .map(|candidate| candidate.surface.as_str())
+        .collect()
+}
+
+#[test]
+fn converts_typed_romaji() {
+    let (client, path) = start("convert");
+
+    let response = type_keys(&client, "kanji");
+    assert_eq!(response.converted_text, "かんじ");
+    assert_eq!(
+        texts(&response),
+        ["漢字", "感じ", "幹事", "かんじ", "カンジ"]
+    );
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn pending_letters_and_backspace() {
+    let (client, path) = start("backspace");
+
+    assert_eq!(type_keys(&client, "nihonn").converted_text, "にほん");
+    assert_eq!(type_keys(&client, "gok").converted_text, "にほんごk");
+    assert_eq!(type_keys(&client, "\u{8}").converted_text, "にほんご");
+
+    let response = type_keys(&client, "\u{8}");
+    assert_eq!(response.converted_text, "にほん");
+    assert_eq!(texts(&response)[0], "日本");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn selected_candidates_are_learned() {
+    let (client, path) = start("learn");
+
+    client
+        .update_context(Context {
+            context: "会議の".to_string(),
+        })
+        .unwrap();
+    let response = type_keys(&client, "kanji");
+    assert_eq!(texts(&response)[2], "幹事");
+
+    client
+        .select_candidate(SelectCandidateRequest {
+            selected_candidate_index: 2,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let response = type_keys(&client, "kanji");
+    assert_eq!(texts(&response)[0], "幹事");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn shift_and_caps_lock_type_uppercase_letters() {
+    let (client, path) = start("shift");
+
+    assert_eq!(type_keys(&client, "kaI").converted_text, "かI");
+
+    let caps_lock = KeyEvent::new(0x41).with_toggles(ipc::ipc_proto::Toggles {
+        caps_lock: true,
+        ..Default::default()
+    });
+    let response = client.convert(caps_lock.into()).unwrap();
+    assert_eq!(response.converted_text, "かIA");
+
+    // Ctrl+A is a shortcut, not input
+    let response = client
+        .convert(KeyEvent::new(0x41).with_modifiers(Modifiers::CTRL).into())
+        .unwrap();
+    assert_eq!(response.converted_text, "かIA");
+
+    // clients that only send the virtual key still work
+    let response = client
+        .convert(ConversionRequest {
+            virtual_key_code: 0x41,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(response.converted_text, "かIAあ");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn resolved_characters_are_used_before_the_virtual_key() {
+    let (client, path) = start("character");
+
+    // Shift+2 on a JIS keyboard, the virtual key alone would not type anything
+    let event = KeyEvent::new(0x32)
+        .with_modifiers(Modifiers::SHIFT)
+        .with_character('"');
+    let response = client.convert(event.into()).unwrap();
+    assert_eq!(response.converted_text, "\"");
+
+    // the JIS minus key still becomes a long vowel mark
+    let event = KeyEvent::new(0xBD).with_character('-');
+    let response = client.convert(event.into()).unwrap();
+    assert_eq!(response.converted_text, "\"ー");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn converts_readings_composed_by_the_client() {
+    let (client, path) = start("reading");
+
+    let response = client
+        .convert(ConversionRequest::from_reading("かんじ"))
+        .unwrap();
+    assert_eq!(response.converted_text, "かんじ");
+    assert_eq!(texts(&response)[0], "漢字");
+
+    // repeated kana are not mistaken for doubled consonants
+    let response = client
+        .convert(ConversionRequest::from_reading("ここ"))
+        .unwrap();
+    assert_eq!(response.converted_text, "ここ");
+
+    // letters typed in the reading are not turned into kana again
+    let response = client
+        .convert(ConversionRequest::from_reading("かna"))
+        .unwrap();
+    assert_eq!(response.converted_text, "かna");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn splits_readings_into_clauses() {
+    let (client, path) = start("clauses");
+
+    let clauses = |response: &ConversionResponse| -> Vec<(String, String)> {
+        response
+            .clauses
+            .iter()
+            .map(|clause| (clause.reading.clone(), clause.surface.clone()))
+            .collect()
+    };
+
+    let response = client
+        .convert(ConversionRequest::from_reading("にほんごをにゅうりょく"))
+        .unwrap();
+    assert_eq!(
+        clauses(&response),
+        [
+            ("にほんご".to_string(), "日本語".to_string()),
+            ("を".to_string(), "を".to_string()),
+            ("にゅうりょく".to_string(), "入力".to_string()),
+        ]
+    );
+    assert_eq!(texts(&response)[0], "日本語");
+
+    // the client fixes the first clause, the rest is split again
+    let request =
+        ConversionRequest::from_reading("にほんごをにゅうりょく").with_clauses(vec![3], 1);
+    let response = client.convert(request).unwrap();
+    assert_eq!(
+        clauses(&response),
+        [
+            ("にほん".to_string(), "日本".to_string()),
+            ("ご".to_string(), "語".to_string()),
+            ("を".to_string(), "を".to_string()),
+            ("にゅうりょく".to_string(), "入力".to_string()),
+        ]
+    );
+    assert_eq!(texts(&response), ["語", "後", "ご", "ゴ"]);
+
+    // the candidates of the focused clause are learned
+    client
+        .select_candidate(SelectCandidateRequest {
+            selected_candidate_index: 1,
+            ..Default::default()
+        })
+        .unwrap();
+    let response = client
+        .convert(ConversionRequest::from_reading("ご"))
+        .unwrap();
+    assert_eq!(texts(&response)[0], "後");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn candidates_for_one_clause_carry_readings_and_annotations() {
+    let (client, path) = start("candidates");
+
+    let response = client
+        .candidates(CandidatesRequest {
+            reading: "にほんご".to_string(),
+        })
+        .unwrap();
+    let candidates: Vec<(&str, &str, u32, &str)> = response
+        .candidates
+        .iter()
+        .map(|candidate| {
+            (
+                candidate.surface.as_str(),
+                candidate.reading.as_str(),
+                candidate.consumed_length,
+                candidate.annotation.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        candidates,
+        [
+            ("日本語", "にほんご", 4, ""),
+            ("にほんご", "にほんご", 4, "ひらがな"),
+            ("ニホンゴ", "にほんご", 4, "カタカナ"),
+            // words for the start of the clause come last
+            ("日本", "にほん", 3, ""),
+            ("二本", "にほん", 3, ""),
+        ]
+    );
+
+    let response = client
+        .candidates(CandidatesRequest {
+            reading: "かぶしきがいしゃ".to_string(),
+        })
+        .unwrap();
+    assert_eq!(response.candidates[1].surface, "㈱");
+    assert_eq!(response.candidates[1].annotation, "環境依存");
+
+    // a prefix candidate is learned under its own reading
+    client
+        .candidates(CandidatesRequest {
+            reading: "にほんご".to_string(),
+        })
+        .unwrap();
+    client
+        .select_candidate(SelectCandidateRequest {
+            selected_candidate_index: 4,
+            ..Default::default()
+        })
+        .unwrap();
+    let response = client
+        .convert(ConversionRequest::from_reading("にほん"))
+        .unwrap();
+    assert_eq!(texts(&response)[0], "二本");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn candidates_sent_with_the_selection_are_learned() {
+    let (client, path) = start("select-candidate");
+
+    // the last list sent was for another clause, the candidate itself wins
+    client
+        .candidates(CandidatesRequest {
+            reading: "にゅうりょく".to_string(),
+        })
+        .unwrap();
+    client
+        .select_candidate(SelectCandidateRequest {
+            selected_candidate_index: 1,
+            candidate: Some(Candidate::new("感じ", "かんじ")),
+        })
+        .unwrap();
+    let response = client
+        .convert(ConversionRequest::from_reading("かんじ"))
+        .unwrap();
+    assert_eq!(texts(&response)[0], "感じ");
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn selecting_keeps_the_conversion() {
+    let (client, path) = start("select-keeps");
+
+    // several clauses of one commit are reported against the same list
+    client
+        .candidates(CandidatesRequest {
+            reading: "にほんご".to_string(),
+        })
+        .unwrap();
+    for index in [4, 2] {
+        client
+            .select_candidate(SelectCandidateRequest {
+                selected_candidate_index: index,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    let response = client
+        .convert(ConversionRequest::from_reading("にほん"))
+        .unwrap();
+    assert_eq!(texts(&response)[0], "二本");
+    let response = client
+        .convert(ConversionRequest::from_reading("にほんご"))
+        .unwrap();
+    assert_eq!(texts(&response)[0], "ニホンゴ");
+
+    let _ = std::fs::remove_file(path);
+}